@@ -0,0 +1,64 @@
+//! Compares the read throughput of a single shard with reads that lock the shard and with
+//! lock-free reads.
+//!
+//! Run with `cargo bench --bench read_contention`. The difference only shows on machines with
+//! several cores.
+
+use plain_cache::{Cache, Config};
+use std::num::NonZero;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const KEY_COUNT: u64 = 1_000;
+const READS_PER_THREAD: u64 = 2_000_000;
+
+fn main() {
+    let max_threads = thread::available_parallelism()
+        .map(NonZero::get)
+        .unwrap_or(1);
+
+    println!("threads  locked reads  lock-free reads");
+    let mut threads = 1;
+    while threads <= max_threads {
+        let locked = reads_per_second(false, threads);
+        let lock_free = reads_per_second(true, threads);
+        println!("{threads:>7}  {locked:>8.1} M/s  {lock_free:>11.1} M/s");
+        threads *= 2;
+    }
+}
+
+fn reads_per_second(lock_free_reads: bool, threads: usize) -> f64 {
+    let config = Config::builder(KEY_COUNT as usize)
+        .shard_count(1)
+        .lock_free_reads(lock_free_reads)
+        .build();
+    let cache = Arc::new(Cache::with_config(config));
+    for key in 0..KEY_COUNT {
+        cache.insert(key, key);
+    }
+
+    let barrier = Arc::new(Barrier::new(threads));
+    let handles: Vec<_> = (0..threads as u64)
+        .map(|thread| {
+            let cache = Arc::clone(&cache);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                let start = Instant::now();
+                for i in 0..READS_PER_THREAD {
+                    std::hint::black_box(cache.get(&((thread + i) % KEY_COUNT)));
+                }
+                start.elapsed()
+            })
+        })
+        .collect();
+
+    let elapsed = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .max()
+        .unwrap_or(Duration::ZERO);
+
+    (threads as u64 * READS_PER_THREAD) as f64 / elapsed.as_secs_f64() / 1_000_000.0
+}
@@ -1,52 +1,94 @@
-use crate::Stats;
-use parking_lot::{Mutex, RwLock};
+use crate::cache::policy::prioritized::PolicyFactory;
+use crate::{
+    Config, ContentionError, EvictionPolicy, PinError, Placement, Priority, ShardStats, Stats,
+};
+use parking_lot::Mutex;
+use read_table::ReadTable;
 use shard::Shard;
+use shard_lock::{ShardLock, ShardWriteGuard};
 use std::borrow::Borrow;
 use std::hash::{BuildHasher, Hash};
 use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 use std::{cmp, thread};
 
+pub(crate) mod config;
 mod entry;
+mod fingerprint_queue;
 mod fixed_size_hash_table;
+#[cfg(feature = "persistence")]
+pub(crate) mod persistence;
+pub(crate) mod pin;
+pub(crate) mod placement;
+pub(crate) mod policy;
+mod read_table;
 mod ring_buffer;
 mod shard;
+pub(crate) mod shard_lock;
+mod slot_list;
+mod slots;
 pub(crate) mod stats;
+mod striped;
 
 pub(crate) type RandomState = ahash::RandomState;
 
 /// Highly performant, thread-safe cache with a focus on simplicity.
 ///
-/// It implements the S3-FIFO eviction algorithm as specified in
+/// By default, it implements the S3-FIFO eviction algorithm as specified in
 /// [FIFO Queues are All You Need for Cache Eviction](https://dl.acm.org/doi/pdf/10.1145/3600006.3613147).
-/// The cache is divided into multiple shards to reduce contention during concurrent access. This
-/// crate does not use any unsafe code.
+/// Other policies can be selected through [`Config::policy`]. The cache is divided into multiple
+/// shards to reduce contention during concurrent access. This crate does not use any unsafe code.
 ///
 /// Wrap the cache in a [`std::sync::Arc`] to share it between threads. Both reads and writes only
 /// require shared references to the cache.
 #[derive(Debug)]
 pub struct Cache<K, V, S = RandomState> {
+    config: Config,
+    capacity: AtomicUsize,
+    unused_capacity: Option<Arc<AtomicUsize>>,
+    // held while capacity moves between shards, so the capacities of the shards add up
+    resizing: Mutex<()>,
     hash_builder: S,
-    shards: Vec<RwLock<Shard<K, V, S>>>,
+    shards: Vec<ShardLock<Shard<K, V>>>,
+    // one per shard with lock-free reads, see `Config::lock_free_reads`
+    read_tables: Vec<Arc<ReadTable<K, V>>>,
     metrics_last_accessed: Mutex<Instant>,
 }
 
 impl<K, V> Cache<K, V, RandomState>
 where
-    K: Clone + Eq + Hash,
+    K: Eq + Hash,
     V: Clone,
 {
-    /// Creates a new cache with at least the specified capacity.
+    /// Creates a new cache with the specified capacity.
     ///
-    /// The actual capacity may be slightly higher due to sharding and rounding.
+    /// The capacity is split between the shards, so a shard may evict entries while others still
+    /// have room. See [`Config::exact_capacity`].
     pub fn with_capacity(capacity: usize) -> Cache<K, V, RandomState> {
         Cache::with_capacity_and_hasher(capacity, Default::default())
     }
+
+    /// Creates a new cache from the specified configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::{Cache, Config};
+    ///
+    /// let cache = Cache::with_config(Config::with_capacity(1000));
+    /// cache.insert("key", "value");
+    /// assert_eq!(cache.get("key"), Some("value"));
+    /// ```
+    pub fn with_config(config: Config) -> Cache<K, V, RandomState> {
+        Cache::with_config_and_hasher(config, Default::default())
+    }
 }
 
 impl<K, V, S> Cache<K, V, S>
 where
-    K: Clone + Eq + Hash,
+    K: Eq + Hash,
     V: Clone,
     S: BuildHasher,
 {
@@ -56,290 +98,1652 @@ where
     ///
     /// If the cache did have this key present, the value is updated, and the old value is returned.
     pub fn insert(&self, key: K, value: V) -> Option<V> {
-        let hash = self.hash_builder.hash_one(&key);
-        let shard_lock = self.get_shard(hash)?;
+        let hash = self.hash(&key);
+        self.insert_with_hash(hash, key, value)
+    }
+
+    /// Inserts a key-value pair into the cache, using a precomputed `hash` of the key.
+    ///
+    /// `hash` must be the value [`Cache::hash`] returns for `key`. Passing any other value does
+    /// not cause undefined behavior, but the entry will not be found by [`Cache::get`] and might
+    /// not be replaced by [`Cache::insert`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    ///
+    /// let hash = cache.hash("key");
+    /// cache.insert_with_hash(hash, "key", "value");
+    ///
+    /// assert_eq!(cache.get_with_hash(hash, "key"), Some("value"));
+    /// ```
+    pub fn insert_with_hash(&self, hash: u64, key: K, value: V) -> Option<V> {
+        let shard_idx = self.get_shard_index(hash)?;
+
+        let mut shard = self.write_shard_for_insert(shard_idx);
+        shard.insert(hash, key, value)
+    }
+
+    /// Inserts a key-value pair into the cache, unless the shard of the key is locked by another
+    /// thread.
+    ///
+    /// Unlike [`Cache::insert`], this method does not wait for the lock of the shard and returns
+    /// [`ContentionError`] instead, dropping the key and the value. It does not wait for other
+    /// shards either, so a full shard evicts an entry instead of borrowing capacity, see
+    /// [`Config::exact_capacity`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    ///
+    /// if cache.try_insert("key", "value").is_err() {
+    ///     // skip the cache fill
+    /// }
+    /// ```
+    pub fn try_insert(&self, key: K, value: V) -> Result<Option<V>, ContentionError> {
+        let hash = self.hash(&key);
+        let Some(shard_lock) = self.get_shard(hash) else {
+            return Ok(None);
+        };
+
+        let mut shard = shard_lock.try_write()?;
+        Ok(shard.insert(hash, key, value))
+    }
+
+    /// Inserts a key-value pair into the cache, together with the cost of recomputing the value
+    /// and the size of the entry.
+    ///
+    /// Cost and size are relative weights in units of your choice, e.g. milliseconds and bytes.
+    /// Only cost-aware policies like [`crate::Policy::Gdsf`] take them into account, all other
+    /// policies treat this method like [`Cache::insert`]. The capacity of the cache is still
+    /// measured in entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::{Cache, Config, Policy};
+    ///
+    /// let config = Config::builder(1000).policy(Policy::Gdsf).build();
+    /// let cache = Cache::with_config(config);
+    ///
+    /// cache.insert_with_cost("aggregate", 42, 500, 1);
+    /// cache.insert_with_cost("lookup", 7, 1, 1);
+    ///
+    /// assert_eq!(cache.get("aggregate"), Some(42));
+    /// ```
+    pub fn insert_with_cost(&self, key: K, value: V, cost: u64, size: u64) -> Option<V> {
+        let hash = self.hash(&key);
+        let shard_idx = self.get_shard_index(hash)?;
+
+        let mut shard = self.write_shard_for_insert(shard_idx);
+        shard.insert_with_cost(hash, key, value, cost, size)
+    }
+
+    /// Inserts a key-value pair into the cache with the specified priority class.
+    ///
+    /// When a shard is full, entries of lower classes are evicted first. [`Cache::insert`]
+    /// inserts entries with [`Priority::Normal`], also when it replaces the value of an entry
+    /// with a different priority.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::{Cache, Priority};
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    ///
+    /// cache.insert_with_priority("interactive", 1, Priority::High);
+    /// for i in 0..10_000 {
+    ///     cache.insert_with_priority("batch", i, Priority::Low);
+    /// }
+    ///
+    /// assert_eq!(cache.get("interactive"), Some(1));
+    /// ```
+    pub fn insert_with_priority(&self, key: K, value: V, priority: Priority) -> Option<V> {
+        let hash = self.hash(&key);
+        let shard_idx = self.get_shard_index(hash)?;
+
+        let mut shard = self.write_shard_for_insert(shard_idx);
+        shard.insert_with_priority(hash, key, value, priority)
+    }
+
+    /// Inserts a key-value pair into the cache and pins it, so it is never evicted.
+    ///
+    /// If the cache did not have this key present, [`None`] is returned. If the cache did have
+    /// this key present, the value is updated, and the old value is returned.
+    ///
+    /// Every shard can hold a limited number of pinned entries, see
+    /// [`Config::max_pinned_percent`]. If the shard of the key is at its limit, the entry is not
+    /// inserted and [`PinError::LimitReached`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    ///
+    /// cache.insert_pinned("feature-flags", "dark-mode").unwrap();
+    /// for i in 0..10_000 {
+    ///     cache.insert("request", "data");
+    /// }
+    ///
+    /// assert_eq!(cache.get("feature-flags"), Some("dark-mode"));
+    /// ```
+    pub fn insert_pinned(&self, key: K, value: V) -> Result<Option<V>, PinError> {
+        let hash = self.hash(&key);
+        let Some(shard_idx) = self.get_shard_index(hash) else {
+            return Err(PinError::LimitReached);
+        };
+
+        let mut shard = self.write_shard_for_insert(shard_idx);
+        shard.insert_pinned(hash, key, value)
+    }
+
+    /// Pins the entry of the key, so it is never evicted.
+    ///
+    /// Pinning an entry that is already pinned succeeds. See [`Cache::insert_pinned`] for the
+    /// limit on pinned entries.
+    pub fn pin<Q>(&self, key: &Q) -> Result<(), PinError>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let hash = self.hash(key);
+        let shard_lock = self.get_shard(hash).ok_or(PinError::NotFound)?;
+
+        let mut shard = shard_lock.write();
+        shard.pin(hash, key)
+    }
+
+    /// Unpins the entry of the key, so it can be evicted again.
+    ///
+    /// The entry is handed back to the eviction policy like a newly inserted entry. Returns
+    /// whether the entry was pinned.
+    pub fn unpin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let hash = self.hash(key);
+        let Some(shard_lock) = self.get_shard(hash) else {
+            return false;
+        };
 
         let mut shard = shard_lock.write();
-        shard.insert(key, value)
+        shard.unpin(hash, key)
+    }
+
+    /// Changes the capacity of the cache while keeping its entries.
+    ///
+    /// The number of shards stays the same, so the capacity of every shard changes. Capacity
+    /// borrowed between shards, see [`Config::exact_capacity`], is split evenly again. Shards of a
+    /// cache shrunk below its number of shards have no capacity left. When
+    /// shrinking, every shard evicts entries in the order of its eviction policy until the
+    /// remaining entries fit. Pinned entries are not evicted, so a shard keeps room for all of its
+    /// pinned entries and one more. The built-in eviction policies keep their state, unless
+    /// entries have to be moved out of slots beyond the new capacity of a shard. Then the policy
+    /// of the shard is rebuilt and state that is not part of its placements, e.g. the costs of
+    /// [`crate::Policy::Gdsf`], is reset.
+    ///
+    /// A cache created with a capacity of zero has no shards and stays empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    /// cache.insert("key", "value");
+    ///
+    /// cache.set_capacity(100_000);
+    ///
+    /// assert_eq!(cache.capacity(), 100_000);
+    /// assert_eq!(cache.get("key"), Some("value"));
+    /// ```
+    pub fn set_capacity(&self, capacity: usize) {
+        let _resizing = self.resizing.lock();
+        self.capacity.store(capacity, Ordering::Release);
+
+        if self.shards.is_empty() {
+            return;
+        }
+
+        let capacities = shard_capacities(capacity, self.shards.len());
+
+        for (shard, capacity) in self.shards.iter().zip(capacities) {
+            shard.write().set_capacity(capacity);
+        }
+    }
+
+    /// Returns the value corresponding to the key.
+    ///
+    /// This method clones the value when returning the item. Consider wrapping your values in
+    /// [`std::sync::Arc`] if cloning is too expensive for you use-case.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let hash = self.hash(key);
+        self.get_with_hash(hash, key)
+    }
+
+    /// Returns the value corresponding to the key, using a precomputed `hash` of the key.
+    ///
+    /// `hash` must be the value [`Cache::hash`] returns for `key`. Otherwise, the entry will not be
+    /// found.
+    pub fn get_with_hash<Q>(&self, hash: u64, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let shard_idx = self.get_shard_index(hash)?;
+        if !self.read_tables.is_empty() {
+            return self.get_lock_free(shard_idx, hash, key);
+        }
+
+        let shard = self.shards[shard_idx].read();
+        shard.get(hash, key)
+    }
+
+    /// Looks up the key in the read table of its shard, without locking the shard. If the read
+    /// buffer of the thread is full, the buffered reads are applied, unless a writer holds the
+    /// shard.
+    fn get_lock_free<Q>(&self, shard_idx: usize, hash: u64, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        let read = self.read_tables[shard_idx].get(hash, key);
+        if read.buffer_full
+            && let Ok(shard) = self.shards[shard_idx].try_read()
+        {
+            shard.apply_reads();
+        }
+
+        read.value
+    }
+
+    /// Returns the value corresponding to the key, unless its shard is locked by another thread.
+    ///
+    /// Unlike [`Cache::get`], this method does not wait for the lock of the shard and returns
+    /// [`ContentionError`] instead, e.g. while an insert evicts entries from the shard. Callers
+    /// on latency-critical paths can treat this like a miss. Caches with
+    /// [`Config::lock_free_reads`] do not lock the shard and never report contention.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    /// cache.insert("key", "value");
+    ///
+    /// let value = cache.try_get("key").unwrap_or(None);
+    /// assert_eq!(value, Some("value"));
+    /// ```
+    pub fn try_get<Q>(&self, key: &Q) -> Result<Option<V>, ContentionError>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let hash = self.hash(key);
+        let Some(shard_idx) = self.get_shard_index(hash) else {
+            return Ok(None);
+        };
+        if !self.read_tables.is_empty() {
+            return Ok(self.get_lock_free(shard_idx, hash, key));
+        }
+
+        let shard = self.shards[shard_idx].try_read()?;
+        Ok(shard.get(hash, key))
+    }
+
+    /// Returns the hash the cache uses for `key`.
+    ///
+    /// The hash is computed with the cache's hasher. It can be passed to
+    /// [`Cache::get_with_hash`] and [`Cache::insert_with_hash`] to avoid hashing the same key
+    /// multiple times. Callers that already hash their keys with the same [`BuildHasher`], e.g.
+    /// for request routing, can create the cache with that hasher and skip this call entirely.
+    pub fn hash<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        self.hash_builder.hash_one(key)
+    }
+
+    /// Inserts many entries at once, placing each of them into the queue given by its
+    /// [`Placement`].
+    ///
+    /// This is meant for warming up a cache, e.g. after a restart. Entries inserted with
+    /// [`Cache::insert`] start out in the small queue and are likely evicted before real traffic
+    /// accesses them. Preloaded entries can instead be placed directly into the main queue and be
+    /// given an initial access count.
+    ///
+    /// The entries are grouped by shard and each shard is locked only once. Existing entries with
+    /// the same keys are replaced. If more entries are preloaded into a shard than it can hold,
+    /// entries are evicted as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::{Cache, Placement};
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    /// cache.preload([
+    ///     ("hot", "value", Placement::MainQueue { frequency: 3 }),
+    ///     ("cold", "value", Placement::SmallQueue { frequency: 0 }),
+    /// ]);
+    ///
+    /// assert_eq!(cache.get("hot"), Some("value"));
+    /// assert_eq!(cache.get("cold"), Some("value"));
+    /// ```
+    pub fn preload<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V, Placement)>,
+    {
+        let entries_per_shard = self.group_by_shard(entries, |(key, _, _)| key);
+
+        for (shard_idx, entries) in entries_per_shard.into_iter().enumerate() {
+            if entries.is_empty() {
+                continue;
+            }
+
+            let mut shard = self.shards[shard_idx].write();
+            for (hash, (key, value, placement)) in entries {
+                shard = self.make_room(shard_idx, shard);
+                shard.insert_with_placement(hash, key, value, placement);
+            }
+        }
+    }
+
+    /// Inserts many key-value pairs at once.
+    ///
+    /// The pairs are grouped by shard and each shard is locked only once, which is considerably
+    /// cheaper than calling [`Cache::insert`] for every pair. Use [`Cache::insert`] if you need the
+    /// previous values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    /// cache.insert_many([("key1", "value1"), ("key2", "value2")]);
+    ///
+    /// assert_eq!(cache.get("key1"), Some("value1"));
+    /// assert_eq!(cache.get("key2"), Some("value2"));
+    /// ```
+    pub fn insert_many<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let entries_per_shard = self.group_by_shard(entries, |(key, _)| key);
+
+        for (shard_idx, entries) in entries_per_shard.into_iter().enumerate() {
+            if entries.is_empty() {
+                continue;
+            }
+
+            let mut shard = self.shards[shard_idx].write();
+            for (hash, (key, value)) in entries {
+                shard = self.make_room(shard_idx, shard);
+                shard.insert(hash, key, value);
+            }
+        }
+    }
+
+    /// Returns the values corresponding to the keys, in the order of the keys.
+    ///
+    /// The keys are grouped by shard and each shard is locked only once, which is considerably
+    /// cheaper than calling [`Cache::get`] for every key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(1000);
+    /// cache.insert("key1", "value1");
+    ///
+    /// let values = cache.get_many(&["key1", "key2"]);
+    /// assert_eq!(values, vec![Some("value1"), None]);
+    /// ```
+    pub fn get_many<Q>(&self, keys: &[Q]) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let mut values = Vec::with_capacity(keys.len());
+        values.resize_with(keys.len(), || None);
+
+        let keys_per_shard = self.group_by_shard(keys.iter().enumerate(), |(_, key)| *key);
+
+        for (shard_idx, keys) in keys_per_shard.into_iter().enumerate() {
+            if keys.is_empty() {
+                continue;
+            }
+
+            if !self.read_tables.is_empty() {
+                for (hash, (idx, key)) in keys {
+                    values[idx] = self.get_lock_free(shard_idx, hash, key);
+                }
+                continue;
+            }
+
+            let shard = self.shards[shard_idx].read();
+            for (hash, (idx, key)) in keys {
+                values[idx] = shard.get(hash, key);
+            }
+        }
+
+        values
+    }
+
+    /// Splits `items` into one group per shard, using `key` to determine the shard of an item.
+    /// Each item is paired with the hash of its key.
+    fn group_by_shard<T, Q, I, F>(&self, items: I, key: F) -> Vec<Vec<(u64, T)>>
+    where
+        I: IntoIterator<Item = T>,
+        F: Fn(&T) -> &Q,
+        Q: ?Sized + Hash,
+    {
+        let mut groups: Vec<Vec<(u64, T)>> = self.shards.iter().map(|_| Vec::new()).collect();
+
+        for item in items {
+            let hash = self.hash_builder.hash_one(key(&item));
+            if let Some(shard_idx) = self.get_shard_index(hash) {
+                groups[shard_idx].push((hash, item));
+            }
+        }
+
+        groups
+    }
+
+    /// Locks the shard for inserting an entry, after borrowing capacity for it if necessary.
+    fn write_shard_for_insert(&self, shard_idx: usize) -> ShardWriteGuard<'_, Shard<K, V>> {
+        self.make_room(shard_idx, self.shards[shard_idx].write())
+    }
+
+    /// Borrows capacity from another shard if the locked shard is full and the cache is not, see
+    /// [`Config::exact_capacity`].
+    ///
+    /// Shards are never locked at the same time, so the lock of the shard is released while
+    /// capacity is borrowed and the shard is locked again afterwards.
+    fn make_room<'a>(
+        &'a self,
+        shard_idx: usize,
+        shard: ShardWriteGuard<'a, Shard<K, V>>,
+    ) -> ShardWriteGuard<'a, Shard<K, V>> {
+        let Some(unused_capacity) = &self.unused_capacity else {
+            return shard;
+        };
+
+        if shard.unused_capacity() > 0 || unused_capacity.load(Ordering::Acquire) == 0 {
+            return shard;
+        }
+
+        drop(shard);
+        self.borrow_capacity(shard_idx);
+        self.shards[shard_idx].write()
+    }
+
+    /// Moves up to half of the unused capacity of the next shard that has any to the specified
+    /// shard.
+    ///
+    /// Only free slots at the end of the lending shard are moved, so neither shard moves entries
+    /// or rebuilds its eviction policies. Borrowing waits for [`Cache::set_capacity`] and other
+    /// borrows, so the capacities of the shards add up to the capacity of the cache once it
+    /// returns.
+    fn borrow_capacity(&self, shard_idx: usize) {
+        let _resizing = self.resizing.lock();
+        let shard_count = self.shards.len();
+
+        for offset in 1..shard_count {
+            let lender_idx = (shard_idx + offset) & (shard_count - 1);
+            if self.shards[lender_idx].read().unused_capacity() == 0 {
+                continue;
+            }
+
+            let lent = self.shards[lender_idx].write().lend_capacity();
+            if lent > 0 {
+                self.shards[shard_idx].write().borrow_capacity(lent);
+                return;
+            }
+        }
+    }
+
+    fn get_shard(&self, hash: u64) -> Option<&ShardLock<Shard<K, V>>> {
+        let shard_idx = self.get_shard_index(hash)?;
+        self.shards.get(shard_idx)
+    }
+
+    fn get_shard_index(&self, hash: u64) -> Option<usize> {
+        if self.shards.is_empty() {
+            return None;
+        }
+
+        Some(shard_index(hash, self.shards.len()))
+    }
+}
+
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Eq + Hash,
+    V: Clone,
+    S: Clone + BuildHasher,
+{
+    /// Creates a new cache with the specified capacity, using `hasher` to hash the keys.
+    ///
+    /// The capacity is split between the shards, so a shard may evict entries while others still
+    /// have room. See [`Config::exact_capacity`].
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Cache<K, V, S> {
+        Cache::with_config_and_hasher(Config::with_capacity(capacity), hash_builder)
+    }
+
+    /// Creates a new cache from the specified configuration, using `hasher` to hash the keys.
+    pub fn with_config_and_hasher(config: Config, hash_builder: S) -> Cache<K, V, S> {
+        let policy_config = config.clone();
+        Cache::with_config_hasher_and_boxed_policy(
+            config,
+            hash_builder,
+            Arc::new(move |capacity| policy::build(&policy_config, capacity)),
+        )
+    }
+
+    /// Creates a new cache from the specified configuration, using `hasher` to hash the keys and
+    /// a custom eviction policy.
+    ///
+    /// `make_policy` is called with the capacity of a shard, once per shard and
+    /// [`Priority`] class in use. See [`EvictionPolicy`] for an example.
+    pub fn with_config_hasher_and_policy<P, F>(
+        config: Config,
+        hash_builder: S,
+        make_policy: F,
+    ) -> Cache<K, V, S>
+    where
+        P: EvictionPolicy + 'static,
+        F: Fn(usize) -> P + Send + Sync + 'static,
+    {
+        Cache::with_config_hasher_and_boxed_policy(
+            config,
+            hash_builder,
+            Arc::new(move |capacity| Box::new(make_policy(capacity))),
+        )
+    }
+
+    fn with_config_hasher_and_boxed_policy(
+        config: Config,
+        hash_builder: S,
+        make_policy: PolicyFactory,
+    ) -> Cache<K, V, S> {
+        let capacity = config.capacity;
+
+        let number_of_shards = number_of_shards(config.shard_count, capacity);
+
+        let mut shards = Vec::with_capacity(number_of_shards);
+        let mut read_tables = Vec::new();
+
+        let metrics_last_accessed = Mutex::new(Instant::now());
+
+        let unused_capacity = config.exact_capacity.then(|| Arc::new(AtomicUsize::new(0)));
+
+        if number_of_shards == 0 {
+            return Self {
+                config,
+                capacity: AtomicUsize::new(capacity),
+                unused_capacity,
+                resizing: Mutex::new(()),
+                hash_builder,
+                shards,
+                read_tables,
+                metrics_last_accessed,
+            };
+        }
+
+        for capacity in shard_capacities(capacity, number_of_shards) {
+            let mut shard = Shard::with_capacity_and_policy(
+                capacity,
+                make_policy.clone(),
+                config.max_pinned_percent,
+            );
+            if config.update_in_place {
+                shard = shard.update_in_place();
+            }
+            if let Some(unused_capacity) = &unused_capacity {
+                shard.track_unused_capacity(unused_capacity.clone());
+            }
+
+            if config.lock_free_reads {
+                let reads = Arc::new(ReadTable::with_capacity(capacity));
+                shard = shard.lock_free_reads(reads.clone());
+                read_tables.push(reads);
+            }
+
+            shards.push(ShardLock::new(shard));
+        }
+
+        Self {
+            config,
+            capacity: AtomicUsize::new(capacity),
+            unused_capacity,
+            resizing: Mutex::new(()),
+            hash_builder,
+            shards,
+            read_tables,
+            metrics_last_accessed,
+        }
+    }
+}
+
+/// Returns the index of the shard of `hash` in a cache with `shard_count` shards.
+///
+/// The number of shards is a power of two. The hash is mixed with a Fibonacci multiplication and
+/// the top bits are used, so hashers with low entropy, e.g. identity hashers, still spread keys
+/// over all shards. Since the index is a prefix of the mixed hash, a shard of a cache with more
+/// shards always maps to the same shard of a cache with fewer shards.
+fn shard_index(hash: u64, shard_count: usize) -> usize {
+    let mixed = hash.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    mixed
+        .checked_shr(u64::BITS - shard_count.ilog2())
+        .unwrap_or(0) as usize
+}
+
+/// Returns the number of shards for a cache with the specified capacity.
+///
+/// The number of shards is a power of two and never exceeds the capacity, so every shard can
+/// hold at least one entry.
+fn number_of_shards(shard_count: Option<usize>, capacity: usize) -> usize {
+    if capacity == 0 {
+        return 0;
+    }
+
+    let shard_count = shard_count.unwrap_or_else(|| {
+        let available_parallelism = thread::available_parallelism()
+            .map(NonZero::get)
+            .unwrap_or(1);
+        available_parallelism * 4
+    });
+
+    let max_shard_count = 1 << capacity.ilog2();
+    cmp::max(shard_count, 1)
+        .checked_next_power_of_two()
+        .map_or(max_shard_count, |shard_count| {
+            cmp::min(shard_count, max_shard_count)
+        })
+}
+
+/// Splits the capacity between the shards, so the capacities of the shards add up to exactly the
+/// capacity of the cache.
+fn shard_capacities(capacity: usize, number_of_shards: usize) -> impl Iterator<Item = usize> {
+    let base = capacity / number_of_shards;
+    let remainder = capacity % number_of_shards;
+    (0..number_of_shards).map(move |shard| base + usize::from(shard < remainder))
+}
+
+impl<K, V, S> Cache<K, V, S> {
+    /// Returns the configuration the cache was created with.
+    ///
+    /// The capacity of the configuration is not updated by [`Cache::set_capacity`], see
+    /// [`Cache::capacity`] instead.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the capacity the cache was created with or last set to with
+    /// [`Cache::set_capacity`].
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Acquire)
+    }
+
+    /// Returns cache performance statistics and resets the internal counters.
+    ///
+    /// This method provides metrics about cache performance since the last call to `stats()`.
+    /// After returning the statistics, all internal counters are reset to zero.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(100);
+    /// cache.insert("key1", "value1");
+    /// cache.get("key1"); // hit
+    /// cache.get("key2"); // miss
+    ///
+    /// let stats = cache.stats();
+    /// println!("Hits: {}, Misses: {}", stats.hit_count, stats.miss_count);
+    /// ```
+    ///
+    /// Calculating hit rate:
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(100);
+    /// cache.insert("key1", "value1");
+    /// cache.get("key1"); // hit
+    /// cache.get("key2"); // miss
+    ///
+    /// let stats = cache.stats();
+    /// let total_requests = stats.hit_count + stats.miss_count;
+    /// if total_requests > 0 {
+    ///     let hit_rate = stats.hit_count as f64 / total_requests as f64;
+    ///     println!("Hit rate: {:.2}%", hit_rate * 100.0);
+    /// }
+    /// ```
+    ///
+    /// # Note
+    ///
+    /// The counters are reset after each call to `stats()`, so each call returns
+    /// statistics for the period since the previous call. This allows for periodic
+    /// monitoring of cache performance.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+
+        let millis_elapsed = {
+            let mut guard = self.metrics_last_accessed.lock();
+            let millis_elapsed = guard.elapsed().as_millis();
+            *guard = Instant::now();
+            millis_elapsed
+        };
+
+        stats.millis_elapsed = millis_elapsed;
+
+        for shard in &self.shards {
+            let shard = shard.read();
+            let mut shard_stats = ShardStats {
+                miss_count: shard.miss_count(),
+                hit_count: shard.hit_count(),
+                eviction_count: shard.eviction_count(),
+                len: shard.len(),
+                capacity: shard.capacity(),
+                pinned_len: shard.pinned_len(),
+                ..ShardStats::default()
+            };
+            shard.reset_counters();
+            shard.take_policy_stats(&mut shard_stats);
+
+            stats.hit_count += shard_stats.hit_count;
+            stats.miss_count += shard_stats.miss_count;
+            stats.eviction_count += shard_stats.eviction_count;
+            stats.ghost_hit_count += shard_stats.ghost_hit_count;
+            stats.main_queue_ghost_hit_count += shard_stats.main_queue_ghost_hit_count;
+            stats.shards.push(shard_stats);
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+    use std::hash::{BuildHasherDefault, Hasher};
+    use std::thread;
+
+    #[test]
+    fn it_inserts_and_gets_basic_values() {
+        // given
+        let cache = Cache::with_capacity(100);
+
+        // when
+        cache.insert("key1", "value1");
+
+        // then
+        assert_eq!(cache.get("key1"), Some("value1"));
+        assert_eq!(cache.get("key2"), None);
+    }
+
+    #[test]
+    fn it_updates_existing_value() {
+        // given
+        let cache = Cache::with_capacity(100);
+        cache.insert("key1", "value1");
+
+        // when
+        let old_value = cache.insert("key1", "new_value");
+
+        // then
+        assert_eq!(old_value, Some("value1"));
+        assert_eq!(cache.get("key1"), Some("new_value"));
+    }
+
+    #[test]
+    fn it_handles_zero_capacity() {
+        // given
+        let cache = Cache::with_capacity(0);
+
+        // when
+        cache.insert("key1", "value1");
+
+        // then
+        assert_eq!(cache.get("key1"), None);
+    }
+
+    #[test]
+    fn it_handles_one_capacity() {
+        // given
+        let cache = Cache::with_capacity(1);
+
+        // when
+        cache.insert("key1", "value1");
+
+        // then
+        assert_eq!(cache.get("key1"), Some("value1"));
+        assert_eq!(cache.get("key2"), None);
+    }
+
+    #[test]
+    fn it_works_with_custom_hasher() {
+        // given
+        use std::collections::hash_map::RandomState;
+        let cache = Cache::with_capacity_and_hasher(100, RandomState::new());
+
+        // when
+        cache.insert("key1", "value1");
+
+        // then
+        assert_eq!(cache.get("key1"), Some("value1"));
+    }
+
+    #[test]
+    fn it_spreads_keys_of_low_entropy_hashers_over_all_shards() {
+        // given
+        #[derive(Default)]
+        struct IdentityHasher(u64);
+
+        impl Hasher for IdentityHasher {
+            fn finish(&self) -> u64 {
+                self.0
+            }
+
+            fn write(&mut self, _bytes: &[u8]) {
+                unimplemented!("only u64 keys are hashed")
+            }
+
+            fn write_u64(&mut self, i: u64) {
+                self.0 = i;
+            }
+        }
+
+        let config = Config {
+            shard_count: Some(8),
+            ..Config::with_capacity(800)
+        };
+        let cache: Cache<u64, u64, _> =
+            Cache::with_config_and_hasher(config, BuildHasherDefault::<IdentityHasher>::default());
+
+        // when
+        for key in 0..800 {
+            cache.insert(key, key);
+        }
+
+        // then
+        let stats = cache.stats();
+        assert!(stats.shards.iter().all(|shard| shard.len > 50), "{stats:?}");
+        assert!(stats.eviction_count < 200, "{}", stats.eviction_count);
+    }
+
+    #[test]
+    fn it_maps_shards_to_the_same_shards_of_caches_with_fewer_shards() {
+        for hash in (0..1_000u64).map(|i| i.wrapping_mul(0x2545_f491_4f6c_dd1d)) {
+            assert_eq!(shard_index(hash, 8) >> 1, shard_index(hash, 4));
+            assert_eq!(shard_index(hash, 8) >> 3, shard_index(hash, 1));
+        }
+    }
+
+    #[test]
+    fn it_is_thread_safe() {
+        // given
+        let cache: Arc<Cache<String, String>> = Arc::new(Cache::with_capacity(1_000));
+        let mut handles = vec![];
+
+        // when
+        for i in 0..5 {
+            let cache_clone = Arc::clone(&cache);
+            let key = format!("key{}", i);
+            let value = format!("value{}", i);
+            let handle = thread::spawn(move || {
+                cache_clone.insert(key.clone(), value.clone());
+                assert_eq!(cache_clone.get(&key), Some(value));
+            });
+            handles.push(handle);
+        }
+
+        // Wait for all threads to complete
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // then
+        for i in 0..5 {
+            let key = format!("key{}", i);
+            let value = format!("value{}", i);
+            assert_eq!(cache.get(&key), Some(value));
+        }
+    }
+
+    #[test]
+    fn it_is_thread_safe_with_lock_free_reads() {
+        // given
+        let config = Config {
+            lock_free_reads: true,
+            ..Config::with_capacity(1_000)
+        };
+        let cache: Arc<Cache<u64, u64>> = Arc::new(Cache::with_config(config));
+
+        // when
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let key = thread * 100 + i;
+                        cache.insert(key, key);
+                        assert_eq!(cache.get(&key), Some(key));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // then
+        assert_eq!(cache.stats().hit_count, 400);
+        assert_eq!(cache.get_many(&[0, 399]), vec![Some(0), Some(399)]);
+    }
+
+    #[test]
+    fn it_reads_without_locking_the_shard() {
+        // given
+        let config = Config {
+            lock_free_reads: true,
+            ..Config::with_capacity(100)
+        };
+        let cache = Cache::with_config(config);
+        cache.insert("key", "value");
+        let shard_idx = cache.get_shard_index(cache.hash("key")).unwrap();
+
+        // when
+        let guard = cache.shards[shard_idx].write();
+        let values = (
+            cache.get("key"),
+            cache.try_get("key"),
+            cache.get_many(&["key", "other"]),
+        );
+        drop(guard);
+
+        // then
+        assert_eq!(
+            values,
+            (Some("value"), Ok(Some("value")), vec![Some("value"), None])
+        );
+        let stats = cache.stats();
+        assert_eq!((stats.hit_count, stats.miss_count), (3, 1));
+    }
+
+    #[test]
+    fn it_keeps_frequently_read_entries_with_lock_free_reads() {
+        // given
+        let config = Config {
+            policy: Policy::Sieve,
+            shard_count: Some(1),
+            lock_free_reads: true,
+            ..Config::with_capacity(2)
+        };
+        let cache = Cache::with_config(config);
+        cache.insert(0, 0);
+        cache.insert(1, 1);
+
+        // when
+        cache.get(&0);
+        cache.insert(2, 2);
+
+        // then
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn it_finds_entries_after_resizing_with_lock_free_reads() {
+        // given
+        let config = Config {
+            shard_count: Some(2),
+            exact_capacity: true,
+            lock_free_reads: true,
+            ..Config::with_capacity(20)
+        };
+        let cache = Cache::with_config(config);
+        for i in 0..20 {
+            cache.insert(i, i);
+        }
+
+        // when
+        cache.set_capacity(10);
+        cache.set_capacity(40);
+
+        // then
+        let found: Vec<_> = (0..20).filter(|i| cache.get(i).is_some()).collect();
+        assert_eq!(found.len(), 10);
+        for i in 100..130 {
+            cache.insert(i, i);
+        }
+        assert!((100..130).all(|i| cache.get(&i) == Some(i)));
+    }
+
+    #[test]
+    fn it_reports_contention_instead_of_blocking() {
+        // given
+        let cache = Cache::with_capacity(100);
+        cache.insert("key", "value");
+        let shard_idx = cache.get_shard_index(cache.hash("key")).unwrap();
+
+        // when
+        let guard = cache.shards[shard_idx].write();
+        let contended = (cache.try_get("key"), cache.try_insert("key", "other"));
+        drop(guard);
+
+        // then
+        assert_eq!(contended, (Err(ContentionError), Err(ContentionError)));
+        assert_eq!(cache.try_insert("key", "other"), Ok(Some("value")));
+        assert_eq!(cache.try_get("key"), Ok(Some("other")));
+    }
+
+    #[test]
+    fn it_respects_capacity_limits() {
+        // given
+        let config = Config {
+            shard_count: Some(1),
+            ..Config::with_capacity(2)
+        };
+        let cache = Cache::with_config(config);
+
+        // when
+        cache.insert("key1", "value1");
+        cache.insert("key2", "value2");
+        cache.insert("key3", "value3");
+        cache.insert("key4", "value4");
+
+        // then
+        assert_eq!(cache.get("key1"), None);
+    }
+
+    #[test]
+    fn it_returns_and_resets_stats() {
+        // given
+        let cache = Cache::with_capacity(1_000);
+
+        // when
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
+
+        // 5 hits
+        for i in 0..5 {
+            cache.get(&i);
+        }
+
+        // 5 misses
+        for i in 10..15 {
+            cache.get(&i);
+        }
+
+        // then
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count, 5);
+        assert_eq!(stats.miss_count, 5);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count, 0);
+        assert_eq!(stats.miss_count, 0);
+    }
+
+    #[test]
+    fn it_returns_stats_per_shard() {
+        // given
+        let cache = Cache::with_capacity(1_000);
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
+        for i in 0..15 {
+            cache.get(&i);
+        }
+
+        // when
+        let stats = cache.stats();
+
+        // then
+        assert_eq!(stats.shards.len(), cache.shards.len());
+        let shard_hits: u64 = stats.shards.iter().map(|shard| shard.hit_count).sum();
+        let shard_misses: u64 = stats.shards.iter().map(|shard| shard.miss_count).sum();
+        let shard_len: usize = stats.shards.iter().map(|shard| shard.len).sum();
+        let shard_capacity: usize = stats.shards.iter().map(|shard| shard.capacity).sum();
+        assert_eq!(shard_hits, stats.hit_count);
+        assert_eq!(shard_misses, stats.miss_count);
+        assert_eq!(shard_len, 10);
+        assert!(shard_capacity >= 1_000);
+    }
+
+    #[test]
+    fn it_preloads_entries_into_the_requested_queue() {
+        // given
+        let cache = Cache::with_capacity(100_000);
+
+        // when
+        cache.preload((0..100).map(|i| (i, i, Placement::MainQueue { frequency: 1 })));
+        cache.preload((100..110).map(|i| (i, i, Placement::SmallQueue { frequency: 0 })));
+
+        // then
+        for i in 0..110 {
+            assert_eq!(cache.get(&i), Some(i));
+        }
+        let stats = cache.stats();
+        assert_eq!(
+            stats.shards.iter().map(|shard| shard.len).sum::<usize>(),
+            110
+        );
+    }
+
+    #[test]
+    fn it_keeps_preloaded_main_queue_entries_when_small_queue_churns() {
+        // given
+        let cache = Cache::with_capacity(100_000);
+        cache.preload((0..100).map(|i| (i, i, Placement::MainQueue { frequency: 0 })));
+
+        // when
+        for i in 1_000..2_000 {
+            cache.insert(i, i);
+        }
+
+        // then
+        for i in 0..100 {
+            assert_eq!(cache.get(&i), Some(i));
+        }
+    }
+
+    #[test]
+    fn it_inserts_and_gets_many_values() {
+        // given
+        let cache = Cache::with_capacity(100_000);
+
+        // when
+        cache.insert_many((0..200).map(|i| (i, i * 2)));
+
+        // then
+        let keys: Vec<i32> = (0..210).collect();
+        let values = cache.get_many(&keys);
+        for (key, value) in keys.iter().zip(values) {
+            let expected = if *key < 200 { Some(key * 2) } else { None };
+            assert_eq!(value, expected);
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count, 200);
+        assert_eq!(stats.miss_count, 10);
+    }
+
+    #[test]
+    fn it_handles_batches_with_zero_capacity() {
+        // given
+        let cache = Cache::with_capacity(0);
+
+        // when
+        cache.insert_many([("key1", "value1")]);
+
+        // then
+        assert_eq!(cache.get_many(&["key1"]), vec![None]);
+    }
+
+    #[test]
+    fn it_uses_precomputed_hashes() {
+        // given
+        let cache = Cache::with_capacity(100);
+        let hash = cache.hash("key1");
+
+        // when
+        cache.insert_with_hash(hash, String::from("key1"), "value1");
+
+        // then
+        assert_eq!(cache.get("key1"), Some("value1"));
+        assert_eq!(cache.get_with_hash(hash, "key1"), Some("value1"));
+        assert_eq!(cache.insert("key1".to_string(), "value2"), Some("value1"));
     }
 
-    /// Returns the value corresponding to the key.
-    ///
-    /// This method clones the value when returning the item. Consider wrapping your values in
-    /// [`std::sync::Arc`] if cloning is too expensive for you use-case.
-    pub fn get<Q>(&self, key: &Q) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: ?Sized + Hash + Eq,
-    {
-        let hash = self.hash_builder.hash_one(key);
-        let shard_lock = self.get_shard(hash)?;
+    #[test]
+    fn it_pins_and_unpins_entries() {
+        // given
+        let cache = Cache::with_capacity(100_000);
+        cache.insert("key1", "value1");
 
-        let shard = shard_lock.read();
-        shard.get(key)
-    }
+        // when
+        let pinned = cache.pin("key1");
+        let inserted = cache.insert_pinned("key2", "value2");
+        let stats = cache.stats();
 
-    fn get_shard(&self, hash: u64) -> Option<&RwLock<Shard<K, V, S>>> {
-        let shard_idx = hash as usize % (cmp::max(self.shards.len(), 2) - 1);
-        self.shards.get(shard_idx)
+        // then
+        assert_eq!(pinned, Ok(()));
+        assert_eq!(inserted, Ok(None));
+        assert_eq!(cache.pin("key3"), Err(PinError::NotFound));
+        assert_eq!(stats.shards.iter().map(|s| s.pinned_len).sum::<usize>(), 2);
+        assert!(cache.unpin("key1"));
+        assert!(!cache.unpin("key1"));
+        assert!(!cache.unpin("key3"));
     }
-}
 
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Clone + Eq + Hash,
-    V: Clone,
-    S: Clone + BuildHasher,
-{
-    /// Creates a new cache with the at least the specified capacity, using `hasher` to hash the
-    /// keys.
-    ///
-    /// The actual capacity may be slightly higher due to sharding and rounding.
-    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Cache<K, V, S> {
-        let available_parallelism = thread::available_parallelism()
-            .map(NonZero::get)
-            .unwrap_or(1);
+    #[test]
+    fn it_does_not_pin_entries_in_caches_without_capacity() {
+        // given
+        let cache = Cache::with_capacity(0);
 
-        let number_of_shards = cmp::min(available_parallelism * 4, capacity);
+        // when
+        let inserted = cache.insert_pinned("key1", "value1");
 
-        let mut shards = Vec::with_capacity(number_of_shards);
+        // then
+        assert_eq!(inserted, Err(PinError::LimitReached));
+    }
 
-        let metrics_last_accessed = Mutex::new(Instant::now());
+    #[test]
+    fn it_keeps_the_policy_state_when_growing() {
+        // given
+        let config = Config {
+            policy: Policy::Gdsf,
+            shard_count: Some(1),
+            ..Config::with_capacity(3)
+        };
+        let cache = Cache::with_config(config);
+        cache.insert_with_cost(0, 0, 1_000, 1);
 
-        if number_of_shards == 0 {
-            return Self {
-                hash_builder,
-                shards,
-                metrics_last_accessed,
-            };
+        // when
+        cache.set_capacity(4);
+        for key in 1..100 {
+            cache.insert(key, key);
         }
 
-        let capacity_per_shard = capacity.div_ceil(number_of_shards);
+        // then
+        assert_eq!(cache.get(&0), Some(0));
+    }
+
+    #[test]
+    fn it_changes_the_capacity() {
+        // given
+        let len = |stats: &Stats| stats.shards.iter().map(|s| s.len).sum::<usize>();
+        let capacity = |stats: &Stats| stats.shards.iter().map(|s| s.capacity).sum::<usize>();
 
-        for _ in 0..number_of_shards {
-            let shard = Shard::with_capacity_and_hasher(capacity_per_shard, hash_builder.clone());
-            shards.push(RwLock::new(shard))
+        let cache = Cache::with_capacity(1_000);
+        for i in 0..1_000 {
+            cache.insert(i, i);
         }
+        let filled = cache.stats();
 
-        Self {
-            hash_builder,
-            shards,
-            metrics_last_accessed,
+        // when
+        cache.set_capacity(100);
+        let shrunk = cache.stats();
+        cache.set_capacity(100_000);
+        for i in 1_000..2_000 {
+            cache.insert(i, i);
         }
-    }
-}
+        let grown = cache.stats();
 
-impl<K, V, S> Cache<K, V, S> {
-    /// Returns cache performance statistics and resets the internal counters.
-    ///
-    /// This method provides metrics about cache performance since the last call to `stats()`.
-    /// After returning the statistics, all internal counters are reset to zero.
-    ///
-    /// # Examples
-    ///
-    /// Basic usage:
-    /// ```
-    /// use plain_cache::Cache;
-    ///
-    /// let cache = Cache::with_capacity(100);
-    /// cache.insert("key1", "value1");
-    /// cache.get("key1"); // hit
-    /// cache.get("key2"); // miss
-    ///
-    /// let stats = cache.stats();
-    /// println!("Hits: {}, Misses: {}", stats.hit_count, stats.miss_count);
-    /// ```
-    ///
-    /// Calculating hit rate:
-    /// ```
-    /// use plain_cache::Cache;
-    ///
-    /// let cache = Cache::with_capacity(100);
-    /// cache.insert("key1", "value1");
-    /// cache.get("key1"); // hit
-    /// cache.get("key2"); // miss
-    ///
-    /// let stats = cache.stats();
-    /// let total_requests = stats.hit_count + stats.miss_count;
-    /// if total_requests > 0 {
-    ///     let hit_rate = stats.hit_count as f64 / total_requests as f64;
-    ///     println!("Hit rate: {:.2}%", hit_rate * 100.0);
-    /// }
-    /// ```
-    ///
-    /// # Note
-    ///
-    /// The counters are reset after each call to `stats()`, so each call returns
-    /// statistics for the period since the previous call. This allows for periodic
-    /// monitoring of cache performance.
-    pub fn stats(&self) -> Stats {
-        let mut stats = Stats::default();
+        // then
+        assert!(len(&shrunk) <= 100);
+        assert_eq!(capacity(&shrunk), 100);
+        assert_eq!(shrunk.eviction_count, (len(&filled) - len(&shrunk)) as u64);
+        assert_eq!(capacity(&grown), 100_000);
+        assert_eq!(grown.eviction_count, 0);
+        assert_eq!(len(&grown), len(&shrunk) + 1_000);
+        assert_eq!(cache.capacity(), 100_000);
+        assert_eq!(cache.config().capacity, 1_000);
+    }
 
-        let millis_elapsed = {
-            let mut guard = self.metrics_last_accessed.lock();
-            let millis_elapsed = guard.elapsed().as_millis();
-            *guard = Instant::now();
-            millis_elapsed
+    #[test]
+    fn it_keeps_main_queue_evictions_apart_when_the_capacity_changes() {
+        // given
+        let config = Config {
+            shard_count: Some(1),
+            remember_main_queue_evictions: true,
+            ..Config::with_capacity(2)
         };
+        let cache = Cache::with_config(config);
+        cache.preload([
+            ("evicted", 1, Placement::MainQueue { frequency: 0 }),
+            ("kept", 2, Placement::MainQueue { frequency: 0 }),
+        ]);
+        cache.insert("new", 3);
 
-        stats.millis_elapsed = millis_elapsed;
-
-        for shard in &self.shards {
-            let shard = shard.read();
-            stats.hit_count += shard.hit_count();
-            stats.miss_count += shard.miss_count();
-            stats.eviction_count += shard.eviction_count();
-            shard.reset_counters();
-        }
+        // when
+        cache.set_capacity(3);
+        cache.insert("evicted", 1);
 
-        stats
+        // then
+        let stats = cache.stats();
+        assert_eq!(stats.ghost_hit_count, 1);
+        assert_eq!(stats.main_queue_ghost_hit_count, 1);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::Arc;
-    use std::thread;
 
     #[test]
-    fn it_inserts_and_gets_basic_values() {
+    fn it_uses_the_capacity_of_all_shards() {
         // given
-        let cache = Cache::with_capacity(100);
+        let config = Config {
+            shard_count: Some(4),
+            ..Config::with_capacity(10)
+        };
+        let cache = Cache::with_config(config);
 
         // when
-        cache.insert("key1", "value1");
+        for i in 0..1_000 {
+            cache.insert(i, i);
+        }
 
         // then
-        assert_eq!(cache.get("key1"), Some("value1"));
-        assert_eq!(cache.get("key2"), None);
+        let stats = cache.stats();
+        let capacities: Vec<_> = stats.shards.iter().map(|shard| shard.capacity).collect();
+        assert_eq!(capacities, vec![3, 3, 2, 2]);
+        assert!(stats.shards.iter().all(|shard| shard.len == shard.capacity));
     }
 
     #[test]
-    fn it_updates_existing_value() {
+    fn it_creates_an_unsharded_cache() {
         // given
-        let cache = Cache::with_capacity(100);
-        cache.insert("key1", "value1");
+        let config = Config {
+            shard_count: Some(1),
+            ..Config::with_capacity(10)
+        };
+        let cache = Cache::with_config(config);
 
         // when
-        let old_value = cache.insert("key1", "new_value");
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
 
         // then
-        assert_eq!(old_value, Some("value1"));
-        assert_eq!(cache.get("key1"), Some("new_value"));
+        let stats = cache.stats();
+        assert_eq!(stats.shards.len(), 1);
+        assert_eq!(stats.eviction_count, 0);
+        assert_eq!(stats.shards[0].len, 10);
     }
 
     #[test]
-    fn it_handles_zero_capacity() {
+    fn it_rounds_the_number_of_shards_to_a_power_of_two() {
+        assert_eq!(number_of_shards(Some(3), 100), 4);
+        assert_eq!(number_of_shards(Some(0), 100), 1);
+        assert_eq!(number_of_shards(Some(64), 10), 8);
+        assert_eq!(number_of_shards(Some(1), 0), 0);
+        assert_eq!(number_of_shards(Some(usize::MAX), 100), 64);
+        assert!(number_of_shards(None, 1_000).is_power_of_two());
+    }
+
+    #[test]
+    fn it_splits_the_capacity_exactly() {
+        assert_eq!(
+            shard_capacities(10, 4).collect::<Vec<_>>(),
+            vec![3, 3, 2, 2]
+        );
+        assert_eq!(shard_capacities(2, 4).collect::<Vec<_>>(), vec![1, 1, 0, 0]);
+        assert_eq!(shard_capacities(8, 4).sum::<usize>(), 8);
+    }
+
+    #[test]
+    fn it_borrows_capacity_from_other_shards() {
         // given
-        let cache = Cache::with_capacity(0);
+        let config = Config {
+            shard_count: Some(4),
+            exact_capacity: true,
+            ..Config::with_capacity(100)
+        };
+        let cache = Cache::with_config(config);
+        let keys_of_first_shard: Vec<u64> = (0..)
+            .filter(|key| cache.get_shard_index(cache.hash(key)) == Some(0))
+            .take(101)
+            .collect();
 
         // when
-        cache.insert("key1", "value1");
+        for key in &keys_of_first_shard[..100] {
+            cache.insert(*key, *key);
+        }
+        let full = cache.stats();
+        cache.insert(keys_of_first_shard[100], 0);
 
         // then
-        assert_eq!(cache.get("key1"), None);
+        assert_eq!(full.eviction_count, 0);
+        assert_eq!(full.shards[0].len, 100);
+        assert_eq!(full.shards.iter().map(|s| s.capacity).sum::<usize>(), 100);
+
+        let stats = cache.stats();
+        assert_eq!(stats.eviction_count, 1);
+        assert_eq!(stats.shards[0].capacity, 100);
     }
 
     #[test]
-    fn it_handles_one_capacity() {
+    fn it_evicts_from_full_shards_without_exact_capacity() {
         // given
-        let cache = Cache::with_capacity(1);
+        let config = Config {
+            shard_count: Some(4),
+            ..Config::with_capacity(100)
+        };
+        let cache = Cache::with_config(config);
 
         // when
-        cache.insert("key1", "value1");
+        for key in (0..)
+            .filter(|key: &u64| cache.get_shard_index(cache.hash(key)) == Some(0))
+            .take(100)
+        {
+            cache.insert(key, key);
+        }
 
         // then
-        assert_eq!(cache.get("key1"), Some("value1"));
-        assert_eq!(cache.get("key2"), None);
+        let stats = cache.stats();
+        assert_eq!(stats.eviction_count, 75);
+        assert_eq!(stats.shards[0].len, 25);
     }
 
     #[test]
-    fn it_works_with_custom_hasher() {
+    fn it_splits_borrowed_capacity_evenly_when_the_capacity_changes() {
         // given
-        use std::collections::hash_map::RandomState;
-        let cache = Cache::with_capacity_and_hasher(100, RandomState::new());
+        let config = Config {
+            shard_count: Some(4),
+            exact_capacity: true,
+            ..Config::with_capacity(100)
+        };
+        let cache = Cache::with_config(config);
+        for key in (0..)
+            .filter(|key: &u64| cache.get_shard_index(cache.hash(key)) == Some(0))
+            .take(100)
+        {
+            cache.insert(key, key);
+        }
 
         // when
-        cache.insert("key1", "value1");
+        cache.set_capacity(200);
+        for key in 0..1_000 {
+            cache.insert(key, key);
+        }
 
         // then
-        assert_eq!(cache.get("key1"), Some("value1"));
+        let stats = cache.stats();
+        let capacities: Vec<_> = stats.shards.iter().map(|shard| shard.capacity).collect();
+        assert_eq!(capacities.iter().sum::<usize>(), 200);
+        assert_eq!(stats.shards.iter().map(|s| s.len).sum::<usize>(), 200);
     }
 
     #[test]
-    fn it_is_thread_safe() {
+    fn it_keeps_its_capacity_when_borrowing_while_the_capacity_changes() {
         // given
-        let cache: Arc<Cache<String, String>> = Arc::new(Cache::with_capacity(1_000));
-        let mut handles = vec![];
+        let config = Config {
+            shard_count: Some(4),
+            exact_capacity: true,
+            ..Config::with_capacity(100)
+        };
+        let cache = Arc::new(Cache::with_config(config));
 
         // when
-        for i in 0..5 {
-            let cache_clone = Arc::clone(&cache);
-            let key = format!("key{}", i);
-            let value = format!("value{}", i);
-            let handle = thread::spawn(move || {
-                cache_clone.insert(key.clone(), value.clone());
-                assert_eq!(cache_clone.get(&key), Some(value));
-            });
-            handles.push(handle);
+        let inserters: Vec<_> = (0..4)
+            .map(|i| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for key in (0..10_000u64).filter(|key| key % 4 == i) {
+                        cache.insert(key, key);
+                    }
+                })
+            })
+            .collect();
+        for capacity in (0..200).map(|i| 50 + i % 100) {
+            cache.set_capacity(capacity);
         }
-
-        // Wait for all threads to complete
-        for handle in handles {
-            handle.join().unwrap();
+        for inserter in inserters {
+            inserter.join().unwrap();
         }
 
         // then
-        for i in 0..5 {
-            let key = format!("key{}", i);
-            let value = format!("value{}", i);
-            assert_eq!(cache.get(&key), Some(value));
-        }
+        let stats = cache.stats();
+        assert_eq!(
+            stats.shards.iter().map(|s| s.capacity).sum::<usize>(),
+            cache.capacity()
+        );
+        assert!(stats.shards.iter().all(|s| s.len <= s.capacity));
     }
 
     #[test]
-    fn it_respects_capacity_limits() {
+    fn it_keeps_refreshed_hot_entries() {
         // given
-        let cache = Cache::with_capacity(2);
+        let config = Config {
+            shard_count: Some(1),
+            update_in_place: true,
+            ..Config::with_capacity(100)
+        };
+        let cache = Cache::with_config(config);
+        cache.insert(0, 0);
+        cache.get(&0);
+        cache.get(&0);
 
         // when
-        cache.insert("key1", "value1");
-        cache.insert("key2", "value2");
-        cache.insert("key3", "value3");
-        cache.insert("key4", "value4");
+        for i in 1..1_000 {
+            cache.insert(i, i);
+            if i % 150 == 0 {
+                assert!(cache.get(&0).is_some());
+                cache.insert(0, i);
+            }
+        }
 
         // then
-        assert_eq!(cache.get("key1"), None);
+        assert_eq!(cache.get(&0), Some(900));
     }
 
     #[test]
-    fn it_returns_and_resets_stats() {
+    fn it_accepts_keys_that_cannot_be_cloned() {
+        #[derive(PartialEq, Eq, Hash, Debug)]
+        struct Key(String);
+
         // given
-        let cache = Cache::with_capacity(1_000);
+        let config = Config {
+            shard_count: Some(1),
+            ..Config::with_capacity(10)
+        };
+        let cache = Cache::with_config(config);
 
         // when
-        for i in 0..10 {
-            cache.insert(i, i);
+        for i in 0..100 {
+            cache.insert(Key(i.to_string()), i);
+            cache.insert(Key(i.to_string()), i + 1);
         }
+        cache.set_capacity(5);
 
-        // 5 hits
-        for i in 0..5 {
-            cache.get(&i);
+        // then
+        assert_eq!(cache.get(&Key(String::from("99"))), Some(100));
+    }
+
+    #[test]
+    fn it_never_clones_keys() {
+        static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(PartialEq, Eq, Hash, Debug)]
+        struct Key(String);
+
+        impl Clone for Key {
+            fn clone(&self) -> Self {
+                CLONES.fetch_add(1, Ordering::Relaxed);
+                Key(self.0.clone())
+            }
         }
 
-        // 5 misses
-        for i in 10..15 {
-            cache.get(&i);
+        for lock_free_reads in [false, true] {
+            // given
+            let config = Config {
+                shard_count: Some(2),
+                exact_capacity: true,
+                lock_free_reads,
+                max_pinned_percent: 50,
+                ..Config::with_capacity(10)
+            };
+            let cache = Cache::with_config(config);
+
+            // when
+            for i in 0..100 {
+                cache.insert(Key(i.to_string()), i);
+                cache.insert(Key(i.to_string()), i + 1);
+                cache.get(&Key(i.to_string()));
+            }
+            cache.insert_pinned(Key(String::from("pinned")), 0).unwrap();
+            cache.set_capacity(5);
+            cache.set_capacity(20);
+
+            // then
+            assert_eq!(CLONES.load(Ordering::Relaxed), 0, "{lock_free_reads}");
         }
+    }
+
+    #[test]
+    fn it_creates_cache_from_config() {
+        // given
+        let config = Config::with_capacity(100);
+
+        // when
+        let cache = Cache::with_config(config.clone());
+        cache.insert("key1", "value1");
 
         // then
-        let stats = cache.stats();
-        assert_eq!(stats.hit_count, 5);
-        assert_eq!(stats.miss_count, 5);
+        assert_eq!(cache.config(), &config);
+        assert_eq!(cache.get("key1"), Some("value1"));
+    }
 
-        let stats = cache.stats();
-        assert_eq!(stats.hit_count, 0);
-        assert_eq!(stats.miss_count, 0);
+    #[test]
+    fn it_evicts_with_the_configured_policy() {
+        for policy in [
+            Policy::S3Fifo,
+            Policy::Sieve,
+            Policy::Lru,
+            Policy::Clock,
+            Policy::Gdsf,
+        ] {
+            // given
+            let config = Config {
+                policy,
+                ..Config::with_capacity(100)
+            };
+            let cache = Cache::with_config(config);
+
+            // when
+            for i in 0..1_000 {
+                cache.insert(i, i);
+            }
+
+            // then
+            let stats = cache.stats();
+            let len: usize = stats.shards.iter().map(|shard| shard.len).sum();
+            assert!(len <= 100, "{policy:?}");
+            assert_eq!(stats.eviction_count, 1_000 - len as u64, "{policy:?}");
+            assert_eq!(cache.get(&999), Some(999), "{policy:?}");
+        }
     }
 }
@@ -0,0 +1,294 @@
+use crate::{Admission, GhostQueue, Policy};
+
+/// Cache configuration.
+///
+/// A configuration can be created in code, see [`Config::builder`], or, with the `serde` feature
+/// enabled, deserialized from a configuration file. Pass it to [`crate::Cache::with_config`] to
+/// create a cache.
+///
+/// New options may be added in minor releases, so configurations cannot be created with struct
+/// expressions outside of this crate.
+///
+/// # Examples
+///
+/// ```
+/// use plain_cache::{Cache, Config, Policy};
+///
+/// let config = Config::builder(1000)
+///     .policy(Policy::Sieve)
+///     .exact_capacity(true)
+///     .build();
+///
+/// let cache = Cache::with_config(config);
+/// cache.insert("key", "value");
+/// assert_eq!(cache.get("key"), Some("value"));
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Config {
+    /// The number of items the cache can hold.
+    pub capacity: usize,
+    /// The eviction policy of the cache. Defaults to [`Policy::S3Fifo`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub policy: Policy,
+    /// The admission filter of the cache. Defaults to [`Admission::AdmitAll`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub admission: Admission,
+    /// Whether [`Policy::S3Fifo`] adapts the size of its small queue to the workload. Defaults to
+    /// `false`, i.e. the small queue holds 10% of the entries. See [`crate::S3Fifo::adaptive`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub adaptive_small_queue: bool,
+    /// The ghost queue of [`Policy::S3Fifo`]. Defaults to [`GhostQueue::DirectMapped`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ghost_queue: GhostQueue,
+    /// Whether [`Policy::S3Fifo`] also remembers keys evicted from its main queue, so they skip
+    /// the small queue when they come back. Defaults to `false`. See
+    /// [`crate::S3Fifo::remember_main_queue_evictions`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub remember_main_queue_evictions: bool,
+    /// The share of every shard, in percent, that pinned entries may occupy. Defaults to `10`.
+    /// At least one entry of every shard remains evictable. See [`crate::Cache::insert_pinned`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "Config::default_max_pinned_percent")
+    )]
+    pub max_pinned_percent: u8,
+    /// The number of shards. Defaults to [`None`], i.e. four times the available parallelism.
+    /// `Some(1)` creates a single, unsharded cache.
+    ///
+    /// The number is rounded up to the next power of two and reduced to at most the capacity.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub shard_count: Option<usize>,
+    /// Whether a full shard borrows unused capacity from other shards instead of evicting.
+    /// Defaults to `false`.
+    ///
+    /// The capacities of the shards always add up to the capacity of the cache, but with uneven
+    /// load, a shard may evict entries while others still have room. With this option, the
+    /// cache only evicts once it holds `capacity` entries. Free slots that shrinking the cache
+    /// leaves in the middle of a shard are not lent until they are filled again.
+    ///
+    /// A full shard borrows free slots from the end of another shard, so no entries move. The
+    /// built-in eviction policies keep their state. Custom policies that do not implement
+    /// [`crate::EvictionPolicy::set_capacity`] are rebuilt like with
+    /// [`crate::Cache::set_capacity`] and lose state that is not part of their placements, e.g.
+    /// costs. Borrows lock the other shards one after another and wait for each other, which is
+    /// why this option is not the default.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub exact_capacity: bool,
+    /// Whether reads find entries without locking their shard. Defaults to `false`.
+    ///
+    /// Every read that locks a shard writes to the lock, so when many cores read from the same
+    /// shard, they contend on its cache line. With this option, every shard also publishes its
+    /// entries in an index that reads use without locking the shard. Reads buffer their accesses
+    /// per thread, and the buffered accesses are applied to the eviction policy in batches, before
+    /// it evicts or when a buffer is full. Accesses are dropped while their buffer is full, so
+    /// under heavy contention the eviction policy only sees a sample of the reads.
+    ///
+    /// Uncontended reads become slower, since they load the entry through atomic reference
+    /// counts. Writes become slower, since they also update the index, and every entry is
+    /// allocated separately and takes about three more words of memory. See the
+    /// `read_contention` benchmark. [`crate::Cache::try_get`] never
+    /// reports contention. Consider it for read-heavy workloads on machines with many cores.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lock_free_reads: bool,
+    /// Whether replacing the value of an entry keeps its position in the eviction policy, e.g.
+    /// its queue and access frequency with [`Policy::S3Fifo`]. Defaults to `false`, i.e. the
+    /// entry is handed to the eviction policy like a new entry and starts over in the small queue.
+    ///
+    /// Updates that change the [`crate::Priority`] of an entry always move it to its new class.
+    /// The cost and size passed to [`crate::Cache::insert_with_cost`] only apply to new entries.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub update_in_place: bool,
+}
+
+impl Config {
+    /// Creates a configuration for a cache with the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Config {
+        Config {
+            capacity,
+            policy: Policy::default(),
+            admission: Admission::default(),
+            adaptive_small_queue: false,
+            ghost_queue: GhostQueue::default(),
+            remember_main_queue_evictions: false,
+            max_pinned_percent: Config::default_max_pinned_percent(),
+            shard_count: None,
+            exact_capacity: false,
+            lock_free_reads: false,
+            update_in_place: false,
+        }
+    }
+
+    /// Returns a builder for a configuration with the specified capacity. Options that are not
+    /// set keep their defaults.
+    pub fn builder(capacity: usize) -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config::with_capacity(capacity),
+        }
+    }
+
+    fn default_max_pinned_percent() -> u8 {
+        10
+    }
+}
+
+/// Builds a [`Config`], see [`Config::builder`].
+///
+/// Every method sets the option of the same name, see the fields of [`Config`].
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn policy(mut self, policy: Policy) -> ConfigBuilder {
+        self.config.policy = policy;
+        self
+    }
+
+    pub fn admission(mut self, admission: Admission) -> ConfigBuilder {
+        self.config.admission = admission;
+        self
+    }
+
+    pub fn adaptive_small_queue(mut self, adaptive_small_queue: bool) -> ConfigBuilder {
+        self.config.adaptive_small_queue = adaptive_small_queue;
+        self
+    }
+
+    pub fn ghost_queue(mut self, ghost_queue: GhostQueue) -> ConfigBuilder {
+        self.config.ghost_queue = ghost_queue;
+        self
+    }
+
+    pub fn remember_main_queue_evictions(mut self, remember: bool) -> ConfigBuilder {
+        self.config.remember_main_queue_evictions = remember;
+        self
+    }
+
+    pub fn max_pinned_percent(mut self, max_pinned_percent: u8) -> ConfigBuilder {
+        self.config.max_pinned_percent = max_pinned_percent;
+        self
+    }
+
+    pub fn shard_count(mut self, shard_count: usize) -> ConfigBuilder {
+        self.config.shard_count = Some(shard_count);
+        self
+    }
+
+    pub fn exact_capacity(mut self, exact_capacity: bool) -> ConfigBuilder {
+        self.config.exact_capacity = exact_capacity;
+        self
+    }
+
+    pub fn lock_free_reads(mut self, lock_free_reads: bool) -> ConfigBuilder {
+        self.config.lock_free_reads = lock_free_reads;
+        self
+    }
+
+    pub fn update_in_place(mut self, update_in_place: bool) -> ConfigBuilder {
+        self.config.update_in_place = update_in_place;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_builds_configurations() {
+        // given
+        let builder = Config::builder(100);
+
+        // when
+        let config = builder
+            .policy(Policy::Lru)
+            .shard_count(4)
+            .lock_free_reads(true)
+            .build();
+
+        // then
+        assert_eq!(
+            config,
+            Config {
+                policy: Policy::Lru,
+                shard_count: Some(4),
+                lock_free_reads: true,
+                ..Config::with_capacity(100)
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_deserializes_from_json() {
+        // given
+        let json = r#"{ "capacity": 1000 }"#;
+
+        // when
+        let config: Config = serde_json::from_str(json).unwrap();
+
+        // then
+        assert_eq!(config, Config::with_capacity(1_000));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_deserializes_the_policy_from_json() {
+        // given
+        let json = r#"{ "capacity": 1000, "policy": "sieve" }"#;
+
+        // when
+        let config: Config = serde_json::from_str(json).unwrap();
+
+        // then
+        assert_eq!(config.policy, Policy::Sieve);
+        assert_eq!(config.admission, Admission::AdmitAll);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_deserializes_the_admission_from_json() {
+        // given
+        let json = r#"{ "capacity": 1000, "admission": "tiny_lfu" }"#;
+
+        // when
+        let config: Config = serde_json::from_str(json).unwrap();
+
+        // then
+        assert_eq!(config.admission, Admission::TinyLfu);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_deserializes_lock_free_reads_from_json() {
+        // given
+        let json = r#"{ "capacity": 1000, "lock_free_reads": true }"#;
+
+        // when
+        let config: Config = serde_json::from_str(json).unwrap();
+
+        // then
+        assert!(config.lock_free_reads);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_roundtrips_through_json() {
+        // given
+        let config = Config::with_capacity(42);
+
+        // when
+        let json = serde_json::to_string(&config).unwrap();
+        let deserialized: Config = serde_json::from_str(&json).unwrap();
+
+        // then
+        assert_eq!(deserialized, config);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::cache::ring_buffer::RingBuffer;
+use hashbrown::HashTable;
+
+/// A FIFO queue of 32-bit key fingerprints with constant-time membership checks.
+///
+/// Once the queue is full, inserting a fingerprint drops the oldest one, so fingerprints are
+/// forgotten in insertion order. Two keys share a fingerprint with a probability of about
+/// `1 / 2^32`, so with `n` fingerprints in the queue, a lookup yields a false positive with a
+/// probability of at most `n / 2^32`.
+///
+/// Like `FixedSizeHashTable`, the queue does not hash anything itself. Callers pass the hash of the key.
+#[derive(Debug)]
+pub(crate) struct FingerprintQueue {
+    queue: RingBuffer<u32>,
+    counts: HashTable<(u32, u32)>,
+}
+
+impl FingerprintQueue {
+    pub(crate) fn with_capacity(capacity: usize) -> FingerprintQueue {
+        FingerprintQueue {
+            queue: RingBuffer::with_capacity(capacity),
+            counts: HashTable::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, hash: u64) {
+        if self.queue.is_full() {
+            let Some(oldest) = self.queue.pop_front() else {
+                // the queue has a capacity of zero
+                return;
+            };
+            self.forget(oldest);
+        }
+
+        let fingerprint = Self::fingerprint(hash);
+        self.queue
+            .push_back(fingerprint)
+            .expect("there must be space after popping the oldest fingerprint");
+
+        match self
+            .counts
+            .find_mut(Self::hash_of(fingerprint), |(f, _)| *f == fingerprint)
+        {
+            Some((_, count)) => *count += 1,
+            None => {
+                self.counts.insert_unique(
+                    Self::hash_of(fingerprint),
+                    (fingerprint, 1),
+                    |(f, _)| Self::hash_of(*f),
+                );
+            }
+        }
+    }
+
+    pub(crate) fn contains(&self, hash: u64) -> bool {
+        let fingerprint = Self::fingerprint(hash);
+        self.counts
+            .find(Self::hash_of(fingerprint), |(f, _)| *f == fingerprint)
+            .is_some()
+    }
+
+    /// Returns the fingerprints from oldest to newest, widened to hashes that map to the same
+    /// fingerprints when inserted again.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.queue.iter().map(|fingerprint| u64::from(*fingerprint))
+    }
+
+    fn forget(&mut self, fingerprint: u32) {
+        let Ok(mut entry) = self
+            .counts
+            .find_entry(Self::hash_of(fingerprint), |(f, _)| *f == fingerprint)
+        else {
+            return;
+        };
+
+        let (_, count) = entry.get_mut();
+        *count -= 1;
+        if *count == 0 {
+            entry.remove();
+        }
+    }
+
+    fn fingerprint(hash: u64) -> u32 {
+        hash as u32
+    }
+
+    /// Spreads the fingerprint over all 64 bits, since the table uses the top bits of the hash.
+    fn hash_of(fingerprint: u32) -> u64 {
+        u64::from(fingerprint).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_forgets_the_oldest_fingerprint_first() {
+        // given
+        let mut queue = FingerprintQueue::with_capacity(2);
+        queue.insert(1);
+        queue.insert(2);
+
+        // when
+        queue.insert(3);
+
+        // then
+        assert!(!queue.contains(1));
+        assert!(queue.contains(2));
+        assert!(queue.contains(3));
+        assert_eq!(queue.iter().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn it_counts_duplicate_fingerprints() {
+        // given
+        let mut queue = FingerprintQueue::with_capacity(3);
+        queue.insert(1);
+        queue.insert(1);
+        queue.insert(2);
+
+        // when
+        queue.insert(3);
+
+        // then
+        assert!(queue.contains(1));
+
+        // when
+        queue.insert(4);
+
+        // then
+        assert!(!queue.contains(1));
+    }
+
+    #[test]
+    fn it_compares_the_lower_32_bits_of_hashes() {
+        // given
+        let mut queue = FingerprintQueue::with_capacity(1);
+
+        // when
+        queue.insert(1 << 40 | 7);
+
+        // then
+        assert!(queue.contains(7));
+        assert!(!queue.contains(8));
+    }
+
+    #[test]
+    fn it_ignores_inserts_with_zero_capacity() {
+        // given
+        let mut queue = FingerprintQueue::with_capacity(0);
+
+        // when
+        queue.insert(1);
+
+        // then
+        assert!(!queue.contains(1));
+    }
+}
@@ -1,55 +1,51 @@
-use crate::cache::RandomState;
 use std::borrow::Borrow;
 use std::cmp;
-use std::hash::{BuildHasher, Hash};
 
+/// A direct-mapped hash table of fixed size. Inserting a value overwrites any value that was stored
+/// in the same bucket.
+///
+/// The table does not hash values itself. Callers pass the hash of a value along with it.
 #[derive(Debug)]
-pub(crate) struct FixedSizeHashTable<T, S = RandomState> {
-    hash_builder: S,
+pub(crate) struct FixedSizeHashTable<T> {
     buckets: Vec<Option<T>>,
 }
 
-impl<T, S> FixedSizeHashTable<T, S>
-where
-    S: BuildHasher + Clone,
-{
-    pub(crate) fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+impl<T> FixedSizeHashTable<T> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
         let mut buckets = Vec::with_capacity(capacity);
         buckets.resize_with(capacity, || None);
 
-        Self {
-            hash_builder,
-            buckets,
-        }
+        Self { buckets }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.buckets.iter().flatten()
     }
 }
 
-impl<T, S> FixedSizeHashTable<T, S>
+impl<T> FixedSizeHashTable<T>
 where
-    T: Eq + Hash,
-    S: BuildHasher,
+    T: Eq,
 {
-    pub(crate) fn insert(&mut self, value: T) {
+    pub(crate) fn insert(&mut self, hash: u64, value: T) {
         if self.buckets.capacity() == 0 {
             return;
         }
 
-        let hash = self.hash_builder.hash_one(&value);
         let bucket_idx = self.get_bucket_index(hash);
 
         self.buckets[bucket_idx] = Some(value);
     }
 
-    pub(crate) fn contains<Q>(&self, value: &Q) -> bool
+    pub(crate) fn contains<Q>(&self, hash: u64, value: &Q) -> bool
     where
         T: Borrow<Q>,
-        Q: ?Sized + Hash + Eq,
+        Q: ?Sized + Eq,
     {
         if self.buckets.capacity() == 0 {
             return false;
         }
 
-        let hash = self.hash_builder.hash_one(value);
         let bucket_idx = self.get_bucket_index(hash);
 
         self.buckets
@@ -67,12 +63,12 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::RandomState;
 
     #[test]
     fn it_initializes_buckets_with_none() {
         // when
-        let hash_table: FixedSizeHashTable<String, RandomState> =
-            FixedSizeHashTable::with_capacity_and_hasher(5, Default::default());
+        let hash_table: FixedSizeHashTable<String> = FixedSizeHashTable::with_capacity(5);
 
         // then
         assert_eq!(hash_table.buckets.len(), 5);
@@ -85,12 +81,15 @@ mod tests {
     #[test]
     fn it_returns_true_for_contained_items() {
         // given
-        let mut hash_table: FixedSizeHashTable<String, RandomState> =
-            FixedSizeHashTable::with_capacity_and_hasher(5, Default::default());
-        hash_table.insert(String::from("hello world"));
+        let hash_builder = RandomState::new();
+        let mut hash_table: FixedSizeHashTable<String> = FixedSizeHashTable::with_capacity(5);
+        hash_table.insert(
+            hash_builder.hash_one("hello world"),
+            String::from("hello world"),
+        );
 
         // when
-        let contained = hash_table.contains("hello world");
+        let contained = hash_table.contains(hash_builder.hash_one("hello world"), "hello world");
 
         // then
         assert!(contained)
@@ -99,12 +98,15 @@ mod tests {
     #[test]
     fn it_returns_false_for_not_contained_items() {
         // given
-        let mut hash_table: FixedSizeHashTable<String, RandomState> =
-            FixedSizeHashTable::with_capacity_and_hasher(5, Default::default());
-        hash_table.insert(String::from("hello world"));
+        let hash_builder = RandomState::new();
+        let mut hash_table: FixedSizeHashTable<String> = FixedSizeHashTable::with_capacity(5);
+        hash_table.insert(
+            hash_builder.hash_one("hello world"),
+            String::from("hello world"),
+        );
 
         // when
-        let contained = hash_table.contains("hello rust");
+        let contained = hash_table.contains(hash_builder.hash_one("hello rust"), "hello rust");
 
         // then
         assert!(!contained)
@@ -113,13 +115,19 @@ mod tests {
     #[test]
     fn it_returns_false_for_overwritten_items() {
         // given
-        let mut hash_table: FixedSizeHashTable<String, RandomState> =
-            FixedSizeHashTable::with_capacity_and_hasher(1, Default::default());
-        hash_table.insert(String::from("hello world"));
-        hash_table.insert(String::from("hello rust"));
+        let hash_builder = RandomState::new();
+        let mut hash_table: FixedSizeHashTable<String> = FixedSizeHashTable::with_capacity(1);
+        hash_table.insert(
+            hash_builder.hash_one("hello world"),
+            String::from("hello world"),
+        );
+        hash_table.insert(
+            hash_builder.hash_one("hello rust"),
+            String::from("hello rust"),
+        );
 
         // when
-        let contained = hash_table.contains("hello world");
+        let contained = hash_table.contains(hash_builder.hash_one("hello world"), "hello world");
 
         // then
         assert!(!contained)
@@ -128,12 +136,15 @@ mod tests {
     #[test]
     fn it_can_handle_zero_capacity() {
         // given
-        let mut hash_table: FixedSizeHashTable<String, RandomState> =
-            FixedSizeHashTable::with_capacity_and_hasher(0, Default::default());
-        hash_table.insert(String::from("hello world"));
+        let hash_builder = RandomState::new();
+        let mut hash_table: FixedSizeHashTable<String> = FixedSizeHashTable::with_capacity(0);
+        hash_table.insert(
+            hash_builder.hash_one("hello world"),
+            String::from("hello world"),
+        );
 
         // when
-        let contained = hash_table.contains("hello world");
+        let contained = hash_table.contains(hash_builder.hash_one("hello world"), "hello world");
 
         // then
         assert!(!contained)
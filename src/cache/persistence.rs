@@ -0,0 +1,644 @@
+use crate::cache::policy::prioritized::Ghost;
+use crate::cache::shard::EntryState;
+use crate::cache::shard_index;
+use crate::{Cache, Placement, Priority};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+
+const MAGIC: [u8; 8] = *b"PLCACHE\0";
+const FORMAT_VERSION: u32 = 1;
+const HASHER_PROBE: &str = "plain-cache";
+
+/// Error returned when saving or loading a cache snapshot fails.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PersistenceError {
+    /// Reading from or writing to the underlying reader or writer failed.
+    Io(io::Error),
+    /// The input does not start with a cache snapshot header.
+    InvalidFormat,
+    /// The snapshot was written in a format version this crate does not understand.
+    UnsupportedVersion(u32),
+    /// The checksum of the snapshot does not match its contents.
+    ChecksumMismatch,
+    /// The cache contents could not be encoded or decoded.
+    Encoding(Box<dyn Error + Send + Sync>),
+}
+
+impl Display for PersistenceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PersistenceError::Io(err) => write!(f, "snapshot I/O failed: {err}"),
+            PersistenceError::InvalidFormat => write!(f, "input is not a cache snapshot"),
+            PersistenceError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot format version {version}")
+            }
+            PersistenceError::ChecksumMismatch => write!(f, "snapshot checksum mismatch"),
+            PersistenceError::Encoding(err) => write!(f, "snapshot encoding failed: {err}"),
+        }
+    }
+}
+
+impl Error for PersistenceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PersistenceError::Io(err) => Some(err),
+            PersistenceError::Encoding(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PersistenceError {
+    fn from(err: io::Error) -> Self {
+        PersistenceError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for PersistenceError {
+    fn from(err: bincode::Error) -> Self {
+        PersistenceError::Encoding(err)
+    }
+}
+
+/// Summary of a snapshot restored with [`Cache::load_from`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub struct LoadReport {
+    /// The number of entries inserted from the snapshot. Fewer entries remain in the cache if it
+    /// is smaller than the cache that wrote the snapshot.
+    pub entry_count: usize,
+    /// Whether the ghost queue of the snapshot was restored. It is skipped if the cache hashes
+    /// keys differently than the cache that wrote the snapshot, see [`Cache::save_to`].
+    pub ghost_queue_restored: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<K, V> {
+    hasher_probe: u64,
+    shards: Vec<ShardSnapshot<K, V>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ShardSnapshot<K, V> {
+    entries: Vec<EntrySnapshot<K, V>>,
+    ghost_queue: Vec<GhostSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntrySnapshot<K, V> {
+    key: K,
+    value: V,
+    placement: Placement,
+    priority: Priority,
+    pinned: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GhostSnapshot {
+    hash: u64,
+    priority: Priority,
+    /// Whether the key was evicted from the main queue, see
+    /// [`crate::Config::remember_main_queue_evictions`].
+    main_queue: bool,
+}
+
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Serialize,
+    V: Serialize,
+    S: BuildHasher,
+{
+    /// Writes the contents of the cache to `writer`.
+    ///
+    /// The snapshot keeps the eviction state of every entry: its [`Priority`] class, whether it is
+    /// pinned and, for S3-FIFO, the queue it is in and its access count, as well as the ghost
+    /// queues. All shards are read-locked while the snapshot
+    /// is encoded, so writes to the cache wait until the encoding is done.
+    ///
+    /// The ghost queue holds hashes of keys, which are only restored into a cache that hashes keys
+    /// the same way. The default hasher is seeded randomly for every cache, so to keep the ghost
+    /// queue across restarts, create both caches with a hasher with fixed seeds, e.g. with
+    /// [`Cache::with_capacity_and_hasher`] and `ahash::RandomState::with_seeds`.
+    ///
+    /// The snapshot uses a versioned binary format that is protected by a checksum. Use
+    /// [`Cache::load_from`] to restore it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use plain_cache::Cache;
+    ///
+    /// let cache = Cache::with_capacity(100);
+    /// cache.insert(String::from("key"), String::from("value"));
+    ///
+    /// let mut snapshot = Vec::new();
+    /// cache.save_to(&mut snapshot).unwrap();
+    ///
+    /// let restored: Cache<String, String> = Cache::with_capacity(100);
+    /// restored.load_from(snapshot.as_slice()).unwrap();
+    /// assert_eq!(restored.get("key"), Some(String::from("value")));
+    /// ```
+    pub fn save_to<W: Write>(&self, mut writer: W) -> Result<(), PersistenceError> {
+        let shard_guards: Vec<_> = self.shards.iter().map(|shard| shard.read()).collect();
+
+        let snapshot = Snapshot {
+            hasher_probe: self.hasher_probe(),
+            shards: shard_guards
+                .iter()
+                .map(|shard| ShardSnapshot {
+                    entries: shard
+                        .entries_with_state()
+                        .into_iter()
+                        .map(|(entry, state)| EntrySnapshot {
+                            key: &entry.key,
+                            value: &entry.value,
+                            placement: state.placement,
+                            priority: state.priority,
+                            pinned: state.pinned,
+                        })
+                        .collect(),
+                    ghost_queue: shard
+                        .ghosts()
+                        .into_iter()
+                        .map(|ghost| GhostSnapshot {
+                            hash: ghost.hash,
+                            priority: ghost.priority,
+                            main_queue: ghost.main_queue,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+
+        let payload = bincode::serialize(&snapshot)?;
+        drop(snapshot);
+        drop(shard_guards);
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+        writer.write_all(&payload)?;
+        writer.write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl<K, V, S> Cache<K, V, S>
+where
+    S: BuildHasher,
+{
+    /// Returns a value that identifies the hash function of the cache. The ghost queue stores
+    /// hashes, which are only meaningful to caches that hash keys the same way.
+    fn hasher_probe(&self) -> u64 {
+        self.hash_builder.hash_one(HASHER_PROBE)
+    }
+
+    /// Returns the index of the shard that a ghost is restored into, given the index of the shard
+    /// it was saved from and the number of shards of the snapshot.
+    ///
+    /// The FIFO ghost queue only keeps the lower 32 bits of every hash, which do not determine the
+    /// shard, so the shard is derived from the shard the ghost was saved from. Shard indices are
+    /// prefixes of the mixed hash, so this is exact if the cache has at most as many shards as the
+    /// snapshot. Otherwise, the hash selects one of the shards the saved shard was split into.
+    fn ghost_shard_index(
+        &self,
+        hash: u64,
+        saved_shard_idx: usize,
+        saved_shard_count: usize,
+    ) -> Option<usize> {
+        if self.shards.is_empty() {
+            return None;
+        }
+
+        let shard_idx = shard_index(hash, self.shards.len());
+        let shard_bits = self.shards.len().ilog2();
+        let saved_shard_bits = saved_shard_count.ilog2();
+
+        if shard_bits <= saved_shard_bits {
+            Some(saved_shard_idx >> (saved_shard_bits - shard_bits))
+        } else {
+            let split_bits = shard_bits - saved_shard_bits;
+            Some(saved_shard_idx << split_bits | shard_idx & ((1 << split_bits) - 1))
+        }
+    }
+}
+
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Eq + Hash + DeserializeOwned,
+    V: Clone + DeserializeOwned,
+    S: BuildHasher,
+{
+    /// Restores the contents of a snapshot written by [`Cache::save_to`] into the cache.
+    ///
+    /// Entries are placed into the same queue and [`Priority`] class they were in when the snapshot
+    /// was taken and keep their access counts. The cache does not need to have the same capacity or number of shards
+    /// as the one that wrote the snapshot. If it is smaller, entries are evicted as usual while
+    /// the snapshot is loaded. Existing entries with the same keys are replaced.
+    ///
+    /// Pinned entries are pinned again, up to the limit of every shard, see
+    /// [`crate::Config::max_pinned_percent`]. Entries beyond the limit are restored unpinned.
+    ///
+    /// The ghost queue stores hashes of keys. It is only restored if the cache hashes keys the same
+    /// way as the cache that wrote the snapshot, i.e. if both use a hasher with the same seeds.
+    /// The default hasher is seeded randomly for every cache, so its ghost queue is always
+    /// skipped. The returned [`LoadReport`] tells whether the ghost queue was restored. Hashes of
+    /// the FIFO ghost queue, see [`crate::GhostQueue::Fifo`], are truncated to 32 bits. They are
+    /// restored into the shards they were saved from, or into the right shards of a cache with
+    /// fewer shards. In a cache with more shards, they end up in one of the shards their shard was
+    /// split into, which is not necessarily the shard of the key.
+    ///
+    /// The snapshot is fully read and validated before the cache is modified.
+    pub fn load_from<R: Read>(&self, mut reader: R) -> Result<LoadReport, PersistenceError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(PersistenceError::InvalidFormat);
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(PersistenceError::UnsupportedVersion(version));
+        }
+
+        let mut payload_len = [0u8; 8];
+        reader.read_exact(&mut payload_len)?;
+        let payload_len = u64::from_le_bytes(payload_len);
+
+        let mut payload = Vec::new();
+        reader
+            .by_ref()
+            .take(payload_len)
+            .read_to_end(&mut payload)?;
+        if payload.len() as u64 != payload_len {
+            return Err(PersistenceError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        let mut checksum = [0u8; 4];
+        reader.read_exact(&mut checksum)?;
+        if crc32fast::hash(&payload) != u32::from_le_bytes(checksum) {
+            return Err(PersistenceError::ChecksumMismatch);
+        }
+
+        let snapshot: Snapshot<K, V> = bincode::deserialize(&payload)?;
+
+        let ghost_queue_restored = snapshot.hasher_probe == self.hasher_probe();
+        if ghost_queue_restored {
+            let saved_shard_count = snapshot.shards.len();
+            let ghosts =
+                snapshot.shards.iter().enumerate().flat_map(|(idx, shard)| {
+                    shard.ghost_queue.iter().map(move |ghost| (idx, ghost))
+                });
+            for (saved_shard_idx, ghost) in ghosts {
+                let Some(shard) = self
+                    .ghost_shard_index(ghost.hash, saved_shard_idx, saved_shard_count)
+                    .and_then(|shard_idx| self.shards.get(shard_idx))
+                else {
+                    continue;
+                };
+
+                shard.write().restore_ghost(Ghost {
+                    hash: ghost.hash,
+                    priority: ghost.priority,
+                    main_queue: ghost.main_queue,
+                });
+            }
+        }
+
+        let entries = snapshot.shards.into_iter().flat_map(|shard| shard.entries);
+        let entries_per_shard = self.group_by_shard(entries, |entry| &entry.key);
+        let entry_count = entries_per_shard.iter().map(Vec::len).sum();
+
+        for (shard_idx, entries) in entries_per_shard.into_iter().enumerate() {
+            if entries.is_empty() {
+                continue;
+            }
+
+            let mut shard = self.shards[shard_idx].write();
+            for (hash, entry) in entries {
+                let state = EntryState {
+                    placement: entry.placement,
+                    priority: entry.priority,
+                    pinned: entry.pinned,
+                };
+                shard = self.make_room(shard_idx, shard);
+                shard.restore(hash, entry.key, entry.value, state);
+            }
+        }
+
+        Ok(LoadReport {
+            entry_count,
+            ghost_queue_restored,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::RandomState;
+    use crate::{Config, GhostQueue};
+
+    #[test]
+    fn it_restores_entries() {
+        // given
+        let cache = Cache::with_capacity(1_000);
+        for i in 0..10 {
+            cache.insert(i, i.to_string());
+        }
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<i32, String> = Cache::with_capacity(1_000);
+        restored.load_from(snapshot.as_slice()).unwrap();
+
+        // then
+        for i in 0..10 {
+            assert_eq!(restored.get(&i), Some(i.to_string()));
+        }
+    }
+
+    #[test]
+    fn it_restores_queue_placement_and_access_counts() {
+        // given
+        let cache = Cache::with_capacity(100);
+        cache.insert("small", 1);
+        cache.get("small");
+        cache.preload([("main", 2, Placement::MainQueue { frequency: 2 })]);
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<String, i32> = Cache::with_capacity(100);
+        restored.load_from(snapshot.as_slice()).unwrap();
+
+        // then
+        let placement_of = |key: &str| {
+            let shard = restored.get_shard(restored.hash(key)).unwrap().read();
+            shard
+                .entries_with_state()
+                .into_iter()
+                .find(|(entry, _)| entry.key == key)
+                .map(|(_, state)| state.placement)
+        };
+        assert_eq!(
+            placement_of("main"),
+            Some(Placement::MainQueue { frequency: 2 })
+        );
+        assert_eq!(
+            placement_of("small"),
+            Some(Placement::SmallQueue { frequency: 1 })
+        );
+    }
+
+    #[test]
+    fn it_restores_priority_classes() {
+        // given
+        let hash_builder = RandomState::with_seeds(1, 2, 3, 4);
+        let config = Config {
+            shard_count: Some(1),
+            ..Config::with_capacity(2)
+        };
+        let cache = Cache::with_config_and_hasher(config.clone(), hash_builder.clone());
+        cache.insert_with_priority("evicted", 1, Priority::Low);
+        cache.insert_with_priority("low", 2, Priority::Low);
+        cache.insert_with_priority("high", 3, Priority::High);
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<String, i32> = Cache::with_config_and_hasher(config, hash_builder);
+        restored.load_from(snapshot.as_slice()).unwrap();
+        let ghosts = restored.shards[0].read().ghosts();
+        restored.insert(String::from("normal"), 4);
+
+        // then
+        let ghost = Ghost {
+            hash: restored.hash("evicted"),
+            priority: Priority::Low,
+            main_queue: false,
+        };
+        assert_eq!(ghosts, vec![ghost]);
+        assert_eq!(restored.get("low"), None);
+        assert_eq!(restored.get("high"), Some(3));
+        assert_eq!(restored.get("normal"), Some(4));
+    }
+
+    #[test]
+    fn it_restores_pinned_entries() {
+        // given
+        let cache = Cache::with_capacity(100);
+        cache.insert_pinned(String::from("pinned"), 1).unwrap();
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<String, i32> = Cache::with_capacity(100);
+        restored.load_from(snapshot.as_slice()).unwrap();
+        for i in 0..1_000 {
+            restored.insert(i.to_string(), i);
+        }
+
+        // then
+        let stats = restored.stats();
+        assert_eq!(stats.shards.iter().map(|s| s.pinned_len).sum::<usize>(), 1);
+        assert_eq!(restored.get("pinned"), Some(1));
+    }
+
+    #[test]
+    fn it_restores_the_priority_of_pinned_entries() {
+        // given
+        let config = Config {
+            shard_count: Some(1),
+            ..Config::with_capacity(10)
+        };
+        let cache = Cache::with_config(config.clone());
+        cache.insert_with_priority(String::from("high"), 1, Priority::High);
+        cache.pin("high").unwrap();
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<String, i32> = Cache::with_config(config);
+        restored.load_from(snapshot.as_slice()).unwrap();
+        restored.unpin("high");
+        for i in 0..100 {
+            restored.insert(i.to_string(), i);
+        }
+
+        // then
+        assert_eq!(restored.get("high"), Some(1));
+    }
+
+    #[test]
+    fn it_restores_the_ghost_queue_for_the_same_hasher() {
+        // given
+        let hash_builder = RandomState::with_seeds(1, 2, 3, 4);
+        let cache = Cache::with_capacity_and_hasher(1, hash_builder.clone());
+        cache.insert(String::from("evicted"), 1);
+        cache.insert(String::from("kept"), 2);
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<String, i32> = Cache::with_capacity_and_hasher(1, hash_builder);
+        let report = restored.load_from(snapshot.as_slice()).unwrap();
+
+        // then
+        assert!(report.ghost_queue_restored);
+        assert_eq!(report.entry_count, 1);
+        let shard = restored.shards[0].read();
+        assert!(
+            shard
+                .ghosts()
+                .iter()
+                .any(|ghost| ghost.hash == restored.hash("evicted"))
+        );
+    }
+
+    #[test]
+    fn it_skips_the_ghost_queue_for_a_different_hasher() {
+        // given
+        let cache = Cache::with_capacity_and_hasher(1, RandomState::with_seeds(1, 2, 3, 4));
+        cache.insert(String::from("evicted"), 1);
+        cache.insert(String::from("kept"), 2);
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<String, i32> =
+            Cache::with_capacity_and_hasher(1, RandomState::with_seeds(5, 6, 7, 8));
+        let report = restored.load_from(snapshot.as_slice()).unwrap();
+
+        // then
+        assert!(!report.ghost_queue_restored);
+        let shard = restored.shards[0].read();
+        assert!(shard.ghosts().is_empty());
+        assert_eq!(restored.get("kept"), Some(2));
+    }
+
+    #[test]
+    fn it_restores_the_fifo_ghost_queue_into_the_same_shards() {
+        // given
+        let hash_builder = RandomState::with_seeds(1, 2, 3, 4);
+        let config = Config {
+            shard_count: Some(4),
+            ghost_queue: GhostQueue::Fifo,
+            ..Config::with_capacity(400)
+        };
+        let ghost_counts = |cache: &Cache<u64, u64>| {
+            cache
+                .shards
+                .iter()
+                .map(|shard| shard.read().ghosts().len())
+                .collect::<Vec<_>>()
+        };
+        let cache = Cache::with_config_and_hasher(config.clone(), hash_builder.clone());
+        for i in 0..1_000 {
+            cache.insert(i, i);
+        }
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored = Cache::with_config_and_hasher(config, hash_builder);
+        restored.load_from(snapshot.as_slice()).unwrap();
+        let restored_ghost_counts = ghost_counts(&restored);
+        for i in (0..1_000).rev() {
+            restored.insert(i, i);
+        }
+
+        // then
+        assert_eq!(restored_ghost_counts, ghost_counts(&cache));
+        assert!(restored_ghost_counts.iter().all(|count| *count > 0));
+        assert!(restored.stats().ghost_hit_count > 0);
+    }
+
+    #[test]
+    fn it_restores_main_queue_evictions_into_their_own_ghost_queue() {
+        // given
+        let hash_builder = RandomState::with_seeds(1, 2, 3, 4);
+        let config = Config {
+            shard_count: Some(1),
+            remember_main_queue_evictions: true,
+            ..Config::with_capacity(2)
+        };
+        let cache = Cache::with_config_and_hasher(config.clone(), hash_builder.clone());
+        cache.preload([
+            ("evicted", 1, Placement::MainQueue { frequency: 0 }),
+            ("kept", 2, Placement::MainQueue { frequency: 0 }),
+        ]);
+        cache.insert("new", 3);
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+
+        // when
+        let restored: Cache<String, i32> = Cache::with_config_and_hasher(config, hash_builder);
+        restored.load_from(snapshot.as_slice()).unwrap();
+        restored.insert(String::from("evicted"), 1);
+
+        // then
+        let stats = restored.stats();
+        assert_eq!(stats.ghost_hit_count, 1);
+        assert_eq!(stats.main_queue_ghost_hit_count, 1);
+    }
+
+    #[test]
+    fn it_rejects_invalid_input() {
+        // given
+        let cache: Cache<i32, i32> = Cache::with_capacity(100);
+
+        // when
+        let result = cache.load_from(&b"not a cache snapshot"[..]);
+
+        // then
+        assert!(matches!(result, Err(PersistenceError::InvalidFormat)));
+    }
+
+    #[test]
+    fn it_rejects_unsupported_versions() {
+        // given
+        let cache: Cache<i32, i32> = Cache::with_capacity(100);
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+        snapshot[8..12].copy_from_slice(&42u32.to_le_bytes());
+
+        // when
+        let result = cache.load_from(snapshot.as_slice());
+
+        // then
+        assert!(matches!(
+            result,
+            Err(PersistenceError::UnsupportedVersion(42))
+        ));
+    }
+
+    #[test]
+    fn it_rejects_corrupted_snapshots() {
+        // given
+        let cache = Cache::with_capacity(100);
+        cache.insert(1, 1);
+        let mut snapshot = Vec::new();
+        cache.save_to(&mut snapshot).unwrap();
+        let payload_start = 8 + 4 + 8;
+        snapshot[payload_start] ^= 0xFF;
+
+        // when
+        let restored: Cache<i32, i32> = Cache::with_capacity(100);
+        let result = restored.load_from(snapshot.as_slice());
+
+        // then
+        assert!(matches!(result, Err(PersistenceError::ChecksumMismatch)));
+        assert_eq!(restored.get(&1), None);
+    }
+}
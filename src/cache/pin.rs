@@ -0,0 +1,53 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Error returned when an entry cannot be pinned.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum PinError {
+    /// The cache does not contain the key.
+    NotFound,
+    /// The shard of the key already holds the maximum number of pinned entries. See
+    /// [`crate::Config::max_pinned_percent`].
+    LimitReached,
+}
+
+impl Display for PinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PinError::NotFound => write!(f, "key not found"),
+            PinError::LimitReached => write!(f, "pinned entry limit of the shard reached"),
+        }
+    }
+}
+
+impl Error for PinError {}
+
+/// Returns the number of entries a shard with the specified capacity may pin.
+///
+/// At least one entry of every shard remains evictable, so inserts of unpinned entries always
+/// succeed.
+pub(crate) fn max_pinned(shard_capacity: usize, max_pinned_percent: u8) -> usize {
+    let max_pinned = shard_capacity * usize::from(max_pinned_percent.min(100)) / 100;
+    max_pinned.min(shard_capacity.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_one_entry_evictable() {
+        assert_eq!(max_pinned(10, 100), 9);
+        assert_eq!(max_pinned(10, 200), 9);
+        assert_eq!(max_pinned(1, 100), 0);
+        assert_eq!(max_pinned(0, 100), 0);
+    }
+
+    #[test]
+    fn it_rounds_down() {
+        assert_eq!(max_pinned(10, 10), 1);
+        assert_eq!(max_pinned(19, 10), 1);
+        assert_eq!(max_pinned(100, 0), 0);
+    }
+}
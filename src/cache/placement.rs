@@ -0,0 +1,18 @@
+/// Describes where a preloaded entry is placed in the cache.
+///
+/// See [`crate::Cache::preload`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Placement {
+    /// Places the entry into the small queue. Entries in the small queue are evicted unless they
+    /// are accessed before they reach the end of the queue.
+    SmallQueue {
+        /// The initial access count of the entry. Values above 3 are capped at 3.
+        frequency: u8,
+    },
+    /// Places the entry directly into the main queue.
+    MainQueue {
+        /// The initial access count of the entry. Values above 3 are capped at 3.
+        frequency: u8,
+    },
+}
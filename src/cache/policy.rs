@@ -0,0 +1,299 @@
+use crate::{Config, Placement, ShardStats};
+use std::fmt::Debug;
+
+mod clock;
+mod gdsf;
+mod lru;
+pub(crate) mod prioritized;
+mod s3_fifo;
+mod sieve;
+mod tiny_lfu;
+
+pub use clock::Clock;
+pub use gdsf::Gdsf;
+pub use lru::Lru;
+pub use s3_fifo::S3Fifo;
+pub use sieve::Sieve;
+
+/// The built-in eviction policies.
+///
+/// Select one through [`crate::Config::policy`]. Custom policies can be used with
+/// [`crate::Cache::with_config_hasher_and_policy`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Policy {
+    /// The [`S3Fifo`] policy.
+    #[default]
+    S3Fifo,
+    /// The [`Sieve`] policy.
+    Sieve,
+    /// The [`Lru`] policy.
+    Lru,
+    /// The [`Clock`] policy.
+    Clock,
+    /// The [`Gdsf`] policy.
+    Gdsf,
+}
+
+/// The priority class of an entry.
+///
+/// Every class has its own eviction policy. Under pressure, entries of lower classes are evicted
+/// first. Within a class, the configured policy decides which entry is evicted.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Priority {
+    /// Evicted before all other entries, e.g. for batch traffic.
+    Low,
+    /// The priority of entries inserted with [`crate::Cache::insert`].
+    #[default]
+    Normal,
+    /// Only evicted if there are no entries of lower classes to evict.
+    High,
+}
+
+impl Priority {
+    pub(crate) const COUNT: usize = 3;
+
+    pub(crate) const ALL: [Priority; Priority::COUNT] =
+        [Priority::Low, Priority::Normal, Priority::High];
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Decides whether new entries may displace entries that are already cached.
+///
+/// Select one through [`crate::Config::admission`]. Only [`Policy::S3Fifo`] supports admission
+/// filters, the other policies admit every entry.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Admission {
+    /// Every entry is admitted as decided by the eviction policy.
+    #[default]
+    AdmitAll,
+    /// Entries only enter the main queue of a full shard if their keys were accessed more often
+    /// than the key of the entry that would be evicted for them. Access frequencies are estimated
+    /// with a count-min sketch that is periodically aged. This protects frequently accessed
+    /// entries from scans of keys that are read only once.
+    TinyLfu,
+}
+
+/// The ghost queue of [`Policy::S3Fifo`], which remembers the hashes of keys recently evicted
+/// from the small queue.
+///
+/// Select one through [`crate::Config::ghost_queue`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum GhostQueue {
+    /// A direct-mapped table of key hashes. A hash is forgotten as soon as another hash maps to
+    /// the same bucket.
+    #[default]
+    DirectMapped,
+    /// A FIFO queue of 32-bit key fingerprints. Fingerprints are forgotten in the order in which
+    /// they were added. See [`crate::S3Fifo::fifo_ghost_queue`].
+    Fifo,
+}
+
+/// Creates the built-in eviction policy selected by `config` for a shard with the specified
+/// capacity.
+pub(crate) fn build(config: &Config, capacity: usize) -> Box<dyn EvictionPolicy> {
+    match config.policy {
+        Policy::S3Fifo => {
+            let policy = match config.admission {
+                Admission::AdmitAll => S3Fifo::with_capacity(capacity),
+                Admission::TinyLfu => S3Fifo::with_tiny_lfu(capacity),
+            };
+
+            let policy = match config.ghost_queue {
+                GhostQueue::DirectMapped => policy,
+                GhostQueue::Fifo => policy.fifo_ghost_queue(),
+            };
+
+            let policy = if config.remember_main_queue_evictions {
+                policy.remember_main_queue_evictions()
+            } else {
+                policy
+            };
+
+            if config.adaptive_small_queue {
+                Box::new(policy.adaptive())
+            } else {
+                Box::new(policy)
+            }
+        }
+        Policy::Sieve => Box::new(Sieve::with_capacity(capacity)),
+        Policy::Lru => Box::new(Lru::with_capacity(capacity)),
+        Policy::Clock => Box::new(Clock::with_capacity(capacity)),
+        Policy::Gdsf => Box::new(Gdsf::with_capacity(capacity)),
+    }
+}
+
+/// Decides which entries of a cache shard are evicted.
+///
+/// Every shard owns its own policy instance. The shard stores its entries in a fixed number of
+/// slots, numbered from `0` to the shard capacity, and informs the policy about everything that
+/// happens to them. The policy never sees keys or values, only slot indices and key hashes.
+///
+/// Reads happen concurrently under a shared lock, so [`EvictionPolicy::on_access`] only gets a
+/// shared reference to the policy. Use atomics or other interior mutability to record accesses.
+/// All other hooks are called under the exclusive lock of the shard.
+///
+/// # Examples
+///
+/// A policy that evicts entries in insertion order:
+///
+/// ```
+/// use plain_cache::{Cache, Config, EvictionPolicy, Insertion};
+/// use std::collections::VecDeque;
+///
+/// #[derive(Debug, Default)]
+/// struct Fifo {
+///     queue: VecDeque<usize>,
+/// }
+///
+/// impl EvictionPolicy for Fifo {
+///     fn on_insert(&mut self, slot: usize, _insertion: Insertion) {
+///         self.queue.push_back(slot);
+///     }
+///
+///     fn on_access(&self, _slot: usize) {}
+///
+///     fn evict(&mut self) -> Option<usize> {
+///         self.queue.pop_front()
+///     }
+///
+///     fn on_remove(&mut self, slot: usize) {
+///         self.queue.retain(|s| *s != slot);
+///     }
+/// }
+///
+/// let cache: Cache<&str, &str> = Cache::with_config_hasher_and_policy(
+///     Config::with_capacity(100),
+///     Default::default(),
+///     |_shard_capacity| Fifo::default(),
+/// );
+/// cache.insert("key", "value");
+/// assert_eq!(cache.get("key"), Some("value"));
+/// ```
+pub trait EvictionPolicy: Debug + Send + Sync {
+    /// Called after an entry has been stored in `slot`.
+    fn on_insert(&mut self, slot: usize, insertion: Insertion);
+
+    /// Called when the entry in `slot` is read.
+    fn on_access(&self, slot: usize);
+
+    /// Called when the shard is full and an entry has to make room for a new one.
+    ///
+    /// Returns the slot of the entry to evict. The policy must forget about the returned slot. If
+    /// [`None`] is returned, the new entry is not inserted.
+    fn evict(&mut self) -> Option<usize>;
+
+    /// Called when the entry in `slot` is removed for a reason other than eviction, e.g. because
+    /// its value is replaced. The policy must forget about the slot.
+    fn on_remove(&mut self, slot: usize);
+
+    /// Called when slots are added to or removed from the end of the shard, e.g. when the shard
+    /// borrows capacity from another shard, see [`crate::Config::exact_capacity`], or when the
+    /// policy of a [`Priority`] other than the default one is full. Removed slots are never tracked
+    /// by the policy.
+    ///
+    /// Returns whether the policy adapted to the new capacity. A policy that returns `false`, the
+    /// default, is replaced by a new policy for the new capacity, which receives the
+    /// [`EvictionPolicy::placements`] and ghost hashes of the old one.
+    fn set_capacity(&mut self, _capacity: usize) -> bool {
+        false
+    }
+
+    /// Returns the tracked slots together with their placement, in the order in which they should
+    /// be inserted again when a snapshot is restored.
+    ///
+    /// Slots that are not returned are saved in slot order and restored into the small queue.
+    fn placements(&self) -> Vec<(usize, Placement)> {
+        Vec::new()
+    }
+
+    /// Returns the hashes of recently evicted keys the policy remembers, if any.
+    ///
+    /// Hashes of keys evicted from the main queue are returned by
+    /// [`EvictionPolicy::main_queue_ghost_hashes`] instead.
+    fn ghost_hashes(&self) -> Vec<u64> {
+        Vec::new()
+    }
+
+    /// Returns the hashes of keys recently evicted from the main queue, if the policy remembers
+    /// them separately. See [`crate::Config::remember_main_queue_evictions`].
+    fn main_queue_ghost_hashes(&self) -> Vec<u64> {
+        Vec::new()
+    }
+
+    /// Remembers the hash of a recently evicted key, e.g. when a snapshot is restored.
+    fn restore_ghost(&mut self, _hash: u64) {}
+
+    /// Remembers the hash of a key recently evicted from the main queue, e.g. when a snapshot is
+    /// restored. Policies that do not remember main queue evictions separately ignore it.
+    fn restore_main_queue_ghost(&mut self, _hash: u64) {}
+
+    /// Adds the statistics collected by the policy since the last call to `stats` and resets
+    /// them.
+    ///
+    /// Called by [`crate::Cache::stats`] under the shared lock of the shard, concurrently with
+    /// [`EvictionPolicy::on_access`].
+    fn take_stats(&self, _stats: &mut ShardStats) {}
+}
+
+/// Information about an entry that is inserted into a cache shard.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub struct Insertion {
+    /// The hash of the key.
+    pub hash: u64,
+    /// The placement requested by the caller, e.g. through [`crate::Cache::preload`]. [`None`]
+    /// for regular inserts.
+    pub placement: Option<Placement>,
+    /// The cost of recomputing the entry, as passed to [`crate::Cache::insert_with_cost`]. `1`
+    /// for regular inserts.
+    pub cost: u64,
+    /// The size of the entry, as passed to [`crate::Cache::insert_with_cost`]. `1` for regular
+    /// inserts.
+    pub size: u64,
+    /// The priority class of the entry, as passed to [`crate::Cache::insert_with_priority`].
+    /// [`Priority::Normal`] for regular inserts.
+    pub priority: Priority,
+}
+
+impl Insertion {
+    pub(crate) fn new(hash: u64, placement: Option<Placement>) -> Self {
+        Self {
+            hash,
+            placement,
+            cost: 1,
+            size: 1,
+            priority: Priority::default(),
+        }
+    }
+
+    pub(crate) fn with_cost(self, cost: u64, size: u64) -> Self {
+        Self { cost, size, ..self }
+    }
+
+    pub(crate) fn with_priority(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+}
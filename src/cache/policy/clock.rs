@@ -0,0 +1,158 @@
+use crate::Placement;
+use crate::cache::policy::{EvictionPolicy, Insertion};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The CLOCK eviction policy.
+///
+/// Every slot of the shard carries a reference bit that is set when its entry is read. A hand
+/// sweeps over the slots in a circle, clears set reference bits and evicts the first entry whose
+/// bit was not set. CLOCK approximates LRU without moving entries on reads.
+#[derive(Debug)]
+pub struct Clock {
+    referenced: Vec<AtomicBool>,
+    occupied: Vec<bool>,
+    len: usize,
+    hand: usize,
+}
+
+impl Clock {
+    /// Creates the policy for a shard with the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Clock {
+        let mut referenced = Vec::with_capacity(capacity);
+        referenced.resize_with(capacity, AtomicBool::default);
+
+        Clock {
+            referenced,
+            occupied: vec![false; capacity],
+            len: 0,
+            hand: 0,
+        }
+    }
+
+    fn advance_hand(&mut self) {
+        self.hand = (self.hand + 1) % self.occupied.len();
+    }
+}
+
+impl EvictionPolicy for Clock {
+    fn on_insert(&mut self, slot: usize, insertion: Insertion) {
+        let referenced = match insertion.placement {
+            Some(Placement::SmallQueue { frequency } | Placement::MainQueue { frequency }) => {
+                frequency > 0
+            }
+            None => false,
+        };
+
+        self.referenced[slot].store(referenced, Ordering::Release);
+
+        if !self.occupied[slot] {
+            self.occupied[slot] = true;
+            self.len += 1;
+        }
+    }
+
+    fn on_access(&self, slot: usize) {
+        self.referenced[slot].store(true, Ordering::Release);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+
+        loop {
+            let slot = self.hand;
+            self.advance_hand();
+
+            if self.occupied[slot] && !self.referenced[slot].swap(false, Ordering::AcqRel) {
+                self.on_remove(slot);
+                return Some(slot);
+            }
+        }
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        if self.occupied[slot] {
+            self.occupied[slot] = false;
+            self.len -= 1;
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) -> bool {
+        self.referenced.resize_with(capacity, AtomicBool::default);
+        self.occupied.resize(capacity, false);
+        if self.hand >= capacity {
+            self.hand = 0;
+        }
+        true
+    }
+
+    fn placements(&self) -> Vec<(usize, Placement)> {
+        let capacity = self.occupied.len();
+
+        (0..capacity)
+            .map(|offset| (self.hand + offset) % capacity)
+            .filter(|slot| self.occupied[*slot])
+            .map(|slot| {
+                let frequency = u8::from(self.referenced[slot].load(Ordering::Acquire));
+                (slot, Placement::MainQueue { frequency })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(policy: &mut Clock, slot: usize) {
+        policy.on_insert(slot, Insertion::new(slot as u64, None));
+    }
+
+    #[test]
+    fn it_evicts_the_first_unreferenced_entry() {
+        // given
+        let mut policy = Clock::with_capacity(3);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        insert(&mut policy, 2);
+        policy.on_access(0);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(1));
+        assert_eq!(policy.hand, 2);
+    }
+
+    #[test]
+    fn it_clears_reference_bits_while_sweeping() {
+        // given
+        let mut policy = Clock::with_capacity(2);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        policy.on_access(0);
+        policy.on_access(1);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(0));
+        assert!(!policy.referenced[1].load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn it_skips_free_slots() {
+        // given
+        let mut policy = Clock::with_capacity(3);
+        insert(&mut policy, 2);
+
+        // when
+        let evicted = (policy.evict(), policy.evict());
+
+        // then
+        assert_eq!(evicted, (Some(2), None));
+    }
+}
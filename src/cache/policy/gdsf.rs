@@ -0,0 +1,271 @@
+use crate::Placement;
+use crate::cache::policy::{EvictionPolicy, Insertion};
+use std::cmp::{self, Ordering as CmpOrdering};
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The GreedyDual-Size-Frequency (GDSF) eviction policy.
+///
+/// Every entry has the priority `clock + frequency * cost / size` and the entry with the lowest
+/// priority is evicted. `clock` is set to the priority of the last evicted entry, so entries that
+/// have not been accessed for a long time age out, no matter how expensive they are. Cost and size
+/// are taken from [`crate::Cache::insert_with_cost`] and default to `1`.
+///
+/// Reads only increment the frequency of an entry. Priorities are updated lazily when the entry is
+/// about to be evicted, which is correct because frequencies, and therefore priorities, never
+/// decrease.
+#[derive(Debug)]
+pub struct Gdsf {
+    slots: Vec<SlotState>,
+    queue: BinaryHeap<QueueEntry>,
+    clock: f64,
+}
+
+#[derive(Debug, Default)]
+struct SlotState {
+    occupied: bool,
+    generation: u64,
+    cost: u64,
+    size: u64,
+    frequency: AtomicU32,
+    queued_frequency: u32,
+}
+
+#[derive(Debug)]
+struct QueueEntry {
+    priority: f64,
+    slot: usize,
+    generation: u64,
+}
+
+impl Gdsf {
+    /// Creates the policy for a shard with the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Gdsf {
+        let mut slots = Vec::with_capacity(capacity);
+        slots.resize_with(capacity, SlotState::default);
+
+        Gdsf {
+            slots,
+            queue: BinaryHeap::with_capacity(capacity),
+            clock: 0.0,
+        }
+    }
+
+    fn enqueue(&mut self, slot: usize) {
+        let state = &mut self.slots[slot];
+        let frequency = state.frequency.load(Ordering::Acquire);
+        state.queued_frequency = frequency;
+
+        let priority = self.clock + f64::from(frequency) * state.cost as f64 / state.size as f64;
+        self.queue.push(QueueEntry {
+            priority,
+            slot,
+            generation: state.generation,
+        });
+
+        // entries of removed slots are only dropped when they are popped, so rebuild the queue
+        // before they pile up
+        if self.queue.len() > 2 * cmp::max(self.slots.len(), 1) {
+            let slots = &self.slots;
+            self.queue
+                .retain(|entry| slots[entry.slot].generation == entry.generation);
+        }
+    }
+}
+
+impl EvictionPolicy for Gdsf {
+    fn on_insert(&mut self, slot: usize, insertion: Insertion) {
+        let frequency = match insertion.placement {
+            Some(Placement::SmallQueue { frequency } | Placement::MainQueue { frequency }) => {
+                u32::from(frequency) + 1
+            }
+            None => 1,
+        };
+
+        let state = &mut self.slots[slot];
+        state.occupied = true;
+        state.generation += 1;
+        state.cost = insertion.cost;
+        state.size = cmp::max(insertion.size, 1);
+        state.frequency.store(frequency, Ordering::Release);
+
+        self.enqueue(slot);
+    }
+
+    fn on_access(&self, slot: usize) {
+        let _ = self.slots[slot].frequency.fetch_update(
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            |frequency| frequency.checked_add(1),
+        );
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        while let Some(entry) = self.queue.pop() {
+            let state = &self.slots[entry.slot];
+            if !state.occupied || state.generation != entry.generation {
+                continue;
+            }
+
+            if state.frequency.load(Ordering::Acquire) != state.queued_frequency {
+                // the entry was accessed since it was queued and has a higher priority now
+                self.enqueue(entry.slot);
+                continue;
+            }
+
+            self.clock = entry.priority;
+            self.on_remove(entry.slot);
+            return Some(entry.slot);
+        }
+
+        None
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        let state = &mut self.slots[slot];
+        state.occupied = false;
+        state.generation += 1;
+    }
+
+    fn set_capacity(&mut self, capacity: usize) -> bool {
+        if capacity < self.slots.len() {
+            self.queue.retain(|entry| entry.slot < capacity);
+        }
+        self.slots.resize_with(capacity, SlotState::default);
+        true
+    }
+
+    fn placements(&self) -> Vec<(usize, Placement)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.occupied)
+            .map(|(slot, state)| {
+                let accessed = state.frequency.load(Ordering::Acquire).saturating_sub(1);
+                let frequency = u8::try_from(accessed).unwrap_or(u8::MAX);
+                (slot, Placement::MainQueue { frequency })
+            })
+            .collect()
+    }
+}
+
+impl PartialEq for QueueEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for QueueEntry {}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry {
+    // reversed, so the binary heap pops the lowest priority first
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other
+            .priority
+            .total_cmp(&self.priority)
+            .then_with(|| other.generation.cmp(&self.generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(policy: &mut Gdsf, slot: usize, cost: u64) {
+        policy.on_insert(slot, Insertion::new(slot as u64, None).with_cost(cost, 1));
+    }
+
+    #[test]
+    fn it_evicts_the_cheapest_entry() {
+        // given
+        let mut policy = Gdsf::with_capacity(3);
+        insert(&mut policy, 0, 100);
+        insert(&mut policy, 1, 1);
+        insert(&mut policy, 2, 10);
+
+        // when
+        let evicted = (
+            policy.evict(),
+            policy.evict(),
+            policy.evict(),
+            policy.evict(),
+        );
+
+        // then
+        assert_eq!(evicted, (Some(1), Some(2), Some(0), None));
+    }
+
+    #[test]
+    fn it_weighs_cost_by_frequency() {
+        // given
+        let mut policy = Gdsf::with_capacity(2);
+        insert(&mut policy, 0, 10);
+        insert(&mut policy, 1, 3);
+        for _ in 0..4 {
+            policy.on_access(1);
+        }
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(0));
+    }
+
+    #[test]
+    fn it_ages_expensive_entries_out() {
+        // given
+        let mut policy = Gdsf::with_capacity(2);
+        insert(&mut policy, 0, 5);
+
+        // when
+        let mut evictions = Vec::new();
+        for _ in 0..3 {
+            insert(&mut policy, 1, 2);
+            evictions.push(policy.evict().unwrap());
+        }
+
+        // then
+        assert_eq!(evictions, vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn it_ignores_removed_entries() {
+        // given
+        let mut policy = Gdsf::with_capacity(2);
+        insert(&mut policy, 0, 1);
+        insert(&mut policy, 1, 2);
+
+        // when
+        policy.on_remove(0);
+
+        // then
+        assert_eq!(policy.evict(), Some(1));
+        assert_eq!(policy.evict(), None);
+    }
+
+    #[test]
+    fn it_keeps_its_costs_when_the_capacity_changes() {
+        // given
+        let mut policy = Gdsf::with_capacity(2);
+        insert(&mut policy, 0, 100);
+        insert(&mut policy, 1, 1);
+
+        // when
+        assert!(policy.set_capacity(4));
+        insert(&mut policy, 3, 10);
+        policy.on_remove(3);
+        assert!(policy.set_capacity(2));
+
+        // then
+        assert_eq!(policy.evict(), Some(1));
+        assert_eq!(policy.evict(), Some(0));
+        assert_eq!(policy.evict(), None);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::Placement;
+use crate::cache::policy::{EvictionPolicy, Insertion};
+use crate::cache::slot_list::SlotList;
+use crate::cache::striped::{STRIPE_COUNT, Striped};
+use parking_lot::Mutex;
+
+const READ_BUFFER_CAPACITY: usize = 16;
+
+/// The least recently used (LRU) eviction policy.
+///
+/// Entries are kept in a list ordered by their last access and the least recently used entry is
+/// evicted. Reads do not move entries right away. Every thread records its reads in the buffer of
+/// its stripe, and the buffered reads are applied to the list in batches, when a buffer is full or
+/// before the list is changed. If a buffer is full while another thread holds the list, the read
+/// is dropped, so under heavy contention the order is only approximately LRU. Prefer
+/// [`crate::S3Fifo`] or [`crate::Sieve`] unless the workload has a strong recency bias or LRU is
+/// needed as a baseline.
+#[derive(Debug)]
+pub struct Lru {
+    queue: Mutex<SlotList>,
+    reads: Striped<Mutex<Vec<usize>>>,
+}
+
+impl Lru {
+    /// Creates the policy for a shard with the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Lru {
+        Lru {
+            queue: Mutex::new(SlotList::with_capacity(capacity)),
+            reads: Striped::new(STRIPE_COUNT),
+        }
+    }
+
+    /// Moves the entries of all buffered reads to the back of the list.
+    fn apply_reads(&mut self) {
+        let queue = self.queue.get_mut();
+        for reads in self.reads.iter_mut() {
+            move_to_back(queue, reads.get_mut().drain(..));
+        }
+    }
+}
+
+fn move_to_back(queue: &mut SlotList, slots: impl Iterator<Item = usize>) {
+    for slot in slots {
+        // the entry may have been removed after it was read
+        if queue.contains(slot) {
+            queue.remove(slot);
+            queue.push_back(slot);
+        }
+    }
+}
+
+impl EvictionPolicy for Lru {
+    fn on_insert(&mut self, slot: usize, _insertion: Insertion) {
+        self.apply_reads();
+        self.queue.get_mut().push_back(slot);
+    }
+
+    fn on_access(&self, slot: usize) {
+        let mut reads = self.reads.get().lock();
+
+        if reads.len() == READ_BUFFER_CAPACITY {
+            let Some(mut queue) = self.queue.try_lock() else {
+                return;
+            };
+            move_to_back(&mut queue, reads.drain(..));
+        }
+
+        reads.push(slot);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        self.apply_reads();
+        let queue = self.queue.get_mut();
+        let slot = queue.front()?;
+        queue.remove(slot);
+        Some(slot)
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.apply_reads();
+        self.queue.get_mut().remove(slot);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) -> bool {
+        self.apply_reads();
+        self.queue.get_mut().set_capacity(capacity);
+        true
+    }
+
+    fn placements(&self) -> Vec<(usize, Placement)> {
+        let mut queue = self.queue.lock();
+        for reads in self.reads.iter() {
+            move_to_back(&mut queue, reads.lock().drain(..));
+        }
+
+        queue
+            .iter()
+            .map(|slot| (slot, Placement::MainQueue { frequency: 0 }))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn insert(policy: &mut Lru, slot: usize) {
+        policy.on_insert(slot, Insertion::new(slot as u64, None));
+    }
+
+    #[test]
+    fn it_evicts_the_least_recently_used_entry() {
+        // given
+        let mut policy = Lru::with_capacity(3);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        insert(&mut policy, 2);
+        policy.on_access(1);
+        policy.on_access(0);
+
+        // when
+        let evicted = (
+            policy.evict(),
+            policy.evict(),
+            policy.evict(),
+            policy.evict(),
+        );
+
+        // then
+        assert_eq!(evicted, (Some(2), Some(1), Some(0), None));
+    }
+
+    #[test]
+    fn it_forgets_removed_entries() {
+        // given
+        let mut policy = Lru::with_capacity(2);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+
+        // when
+        policy.on_remove(0);
+        policy.on_access(0);
+
+        // then
+        assert_eq!(policy.evict(), Some(1));
+        assert_eq!(policy.evict(), None);
+    }
+
+    #[test]
+    fn it_applies_reads_of_other_threads() {
+        // given
+        let mut policy = Lru::with_capacity(3);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        insert(&mut policy, 2);
+
+        // when
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..READ_BUFFER_CAPACITY * 2 {
+                    policy.on_access(0);
+                }
+            });
+        });
+
+        // then
+        assert_eq!(policy.evict(), Some(1));
+        assert_eq!(policy.evict(), Some(2));
+        assert_eq!(policy.evict(), Some(0));
+    }
+}
@@ -0,0 +1,609 @@
+use crate::cache::policy::{EvictionPolicy, Insertion, Priority};
+use crate::{Placement, ShardStats};
+use parking_lot::Mutex;
+use std::cmp;
+use std::fmt::{Debug, Formatter};
+use std::mem;
+use std::sync::Arc;
+
+pub(crate) type PolicyFactory = Arc<dyn Fn(usize) -> Box<dyn EvictionPolicy> + Send + Sync>;
+
+/// The hash of a recently evicted key, together with the ghost queue that remembers it.
+#[cfg(feature = "persistence")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Ghost {
+    pub(crate) hash: u64,
+    pub(crate) priority: Priority,
+    /// Whether the key was evicted from the main queue, see
+    /// [`EvictionPolicy::main_queue_ghost_hashes`].
+    pub(crate) main_queue: bool,
+}
+
+/// The capacity of the policy of a class other than [`Priority::default`] when it is created.
+const INITIAL_CLASS_CAPACITY: usize = 16;
+
+/// Marks entries of the shard that no policy tracks, e.g. pinned entries.
+const UNTRACKED: usize = usize::MAX;
+
+/// Runs a separate eviction policy for every [`Priority`] and evicts from the lowest class that
+/// has an entry to evict. Policies are only created once the first entry of their class is
+/// inserted.
+///
+/// The policy of [`Priority::default`], which usually holds most entries, tracks the slots of the
+/// shard and is created with the full shard capacity. The policies of the other classes number
+/// their entries separately, starting at zero, and are created with a small capacity that doubles
+/// whenever they are full, see [`EvictionPolicy::set_capacity`]. So classes that hold few entries
+/// also take little memory. Policies that cannot change their capacity are replaced by a new one
+/// with the doubled capacity, which receives the entries and ghost hashes of the old one.
+///
+/// When slots are added to or removed from the end of the shard, the policy of the default class
+/// adapts to the new capacity. When entries move between slots, it is replaced by a new policy for
+/// the new capacity, which receives the entries and ghost hashes of the old one. State that is not
+/// part of [`EvictionPolicy::placements`], e.g. GDSF costs, is reset. The policies of the other
+/// classes keep their state, since the numbers of their entries do not change.
+pub(crate) struct Prioritized {
+    capacity: usize,
+    make_policy: PolicyFactory,
+    classes: [Option<Class>; Priority::COUNT],
+    slots: Vec<SlotClass>,
+    // statistics of replaced policies that have not been taken yet
+    pending_stats: Mutex<ShardStats>,
+}
+
+/// The eviction policy of a class.
+struct Class {
+    policy: Box<dyn EvictionPolicy>,
+    capacity: usize,
+    /// The slots of the shard and the hashes of the entries the policy tracks, indexed by the
+    /// slots of the policy. [`None`] for the default class, whose policy tracks the slots of the
+    /// shard.
+    entries: Option<Vec<(usize, u64)>>,
+    free_slots: Vec<usize>,
+}
+
+/// The class of the entry in a slot of the shard and its slot in the policy of the class.
+#[derive(Clone, Copy, Debug)]
+struct SlotClass {
+    priority: Priority,
+    class_slot: usize,
+}
+
+impl Default for SlotClass {
+    fn default() -> Self {
+        SlotClass {
+            priority: Priority::default(),
+            class_slot: UNTRACKED,
+        }
+    }
+}
+
+impl Prioritized {
+    pub(crate) fn new(capacity: usize, make_policy: PolicyFactory) -> Prioritized {
+        Prioritized {
+            capacity,
+            make_policy,
+            classes: Default::default(),
+            slots: vec![SlotClass::default(); capacity],
+            pending_stats: Mutex::default(),
+        }
+    }
+
+    /// Replaces the policy of the default class with a policy for the new capacity and moves the
+    /// entries of the other classes to their new slots.
+    ///
+    /// `relocations` maps every old slot whose entry stays tracked to its new slot and the hash of
+    /// its key. Entries without a relocation are removed.
+    pub(crate) fn resize(&mut self, capacity: usize, relocations: &[Option<(usize, u64)>]) {
+        let old_slots = mem::replace(&mut self.slots, vec![SlotClass::default(); capacity]);
+        self.capacity = capacity;
+
+        let default_index = Priority::default().index();
+        let mut restored = vec![false; relocations.len()];
+        if let Some(old_class) = self.classes[default_index].take() {
+            let (policy, default_restored) = rebuild(
+                old_class.policy,
+                capacity,
+                relocations,
+                &self.make_policy,
+                self.pending_stats.get_mut(),
+            );
+            for (old_slot, class_slot) in default_restored.into_iter().enumerate() {
+                if let Some(new_slot) = class_slot {
+                    restored[old_slot] = true;
+                    self.slots[new_slot] = SlotClass {
+                        priority: Priority::default(),
+                        class_slot: new_slot,
+                    };
+                }
+            }
+            self.classes[default_index] = Some(Class {
+                policy,
+                capacity,
+                entries: None,
+                free_slots: Vec::new(),
+            });
+        }
+
+        for priority in Priority::ALL {
+            let Some(class) = &mut self.classes[priority.index()] else {
+                continue;
+            };
+            let Some(entries) = &mut class.entries else {
+                continue;
+            };
+
+            for (class_slot, entry) in entries.iter_mut().enumerate() {
+                if entry.0 == UNTRACKED {
+                    continue;
+                }
+
+                match relocations.get(entry.0).copied().flatten() {
+                    Some((new_slot, hash)) => {
+                        restored[entry.0] = true;
+                        *entry = (new_slot, hash);
+                        self.slots[new_slot] = SlotClass {
+                            priority,
+                            class_slot,
+                        };
+                    }
+                    None => {
+                        class.policy.on_remove(class_slot);
+                        *entry = (UNTRACKED, 0);
+                        class.free_slots.push(class_slot);
+                    }
+                }
+            }
+        }
+
+        // entries the old policies did not report placements for
+        for (old_slot, relocation) in relocations.iter().enumerate() {
+            if let (Some((new_slot, hash)), false) = (relocation, restored[old_slot]) {
+                let priority = old_slots[old_slot].priority;
+                let placement = Placement::SmallQueue { frequency: 0 };
+                let insertion = Insertion::new(*hash, Some(placement)).with_priority(priority);
+                self.on_insert(*new_slot, insertion);
+            }
+        }
+    }
+
+    /// Sets the class of an entry that no policy tracks, e.g. a pinned entry. The class is kept
+    /// until the entry is handed to a policy again, e.g. when it is unpinned.
+    pub(crate) fn set_priority(&mut self, slot: usize, priority: Priority) {
+        self.slots[slot].priority = priority;
+    }
+
+    /// Returns the class of the entry in the specified slot.
+    pub(crate) fn priority_of(&self, slot: usize) -> Priority {
+        self.slots[slot].priority
+    }
+
+    /// Returns the ghost hashes of all classes.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn ghosts(&self) -> Vec<Ghost> {
+        let mut ghosts = Vec::new();
+
+        for priority in Priority::ALL {
+            let Some(class) = &self.classes[priority.index()] else {
+                continue;
+            };
+
+            let ghost = |main_queue| {
+                move |hash| Ghost {
+                    hash,
+                    priority,
+                    main_queue,
+                }
+            };
+            ghosts.extend(class.policy.ghost_hashes().into_iter().map(ghost(false)));
+            ghosts.extend(
+                class
+                    .policy
+                    .main_queue_ghost_hashes()
+                    .into_iter()
+                    .map(ghost(true)),
+            );
+        }
+
+        ghosts
+    }
+
+    /// Hands a ghost hash to the policy of its class.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn restore(&mut self, ghost: Ghost) {
+        let policy = &mut self.class_mut(ghost.priority).policy;
+        if ghost.main_queue {
+            policy.restore_main_queue_ghost(ghost.hash);
+        } else {
+            policy.restore_ghost(ghost.hash);
+        }
+    }
+
+    fn class_mut(&mut self, priority: Priority) -> &mut Class {
+        let (capacity, make_policy) = (self.capacity, &self.make_policy);
+        self.classes[priority.index()].get_or_insert_with(|| {
+            if priority == Priority::default() {
+                Class {
+                    policy: make_policy(capacity),
+                    capacity,
+                    entries: None,
+                    free_slots: Vec::new(),
+                }
+            } else {
+                let capacity = cmp::min(capacity, INITIAL_CLASS_CAPACITY);
+                Class {
+                    policy: make_policy(capacity),
+                    capacity,
+                    entries: Some(Vec::with_capacity(capacity)),
+                    free_slots: Vec::new(),
+                }
+            }
+        })
+    }
+
+    /// Returns the slot in the policy of `priority` for a new entry in `slot`. Doubles the
+    /// capacity of the policy if it is full.
+    fn allocate_class_slot(&mut self, priority: Priority, slot: usize, hash: u64) -> usize {
+        let shard_capacity = self.capacity;
+        let make_policy = self.make_policy.clone();
+        let pending_stats = self.pending_stats.get_mut();
+        let class = self.classes[priority.index()]
+            .as_mut()
+            .expect("the class is created before its entries are inserted");
+        let Some(entries) = &mut class.entries else {
+            return slot;
+        };
+
+        if let Some(class_slot) = class.free_slots.pop() {
+            entries[class_slot] = (slot, hash);
+            return class_slot;
+        }
+
+        if entries.len() == class.capacity {
+            let capacity = cmp::max(
+                cmp::min(class.capacity * 2, shard_capacity),
+                class.capacity + 1,
+            );
+            if !class.policy.set_capacity(capacity) {
+                let relocations: Vec<_> = entries
+                    .iter()
+                    .enumerate()
+                    .map(|(class_slot, (slot, hash))| {
+                        (*slot != UNTRACKED).then_some((class_slot, *hash))
+                    })
+                    .collect();
+                let old_policy = mem::replace(&mut class.policy, make_policy(0));
+                let (policy, restored) = rebuild(
+                    old_policy,
+                    capacity,
+                    &relocations,
+                    &make_policy,
+                    pending_stats,
+                );
+                class.policy = policy;
+
+                // entries the old policy did not report placements for
+                for (class_slot, relocation) in relocations.iter().enumerate() {
+                    if let (Some((_, hash)), None) = (relocation, restored[class_slot]) {
+                        let placement = Placement::SmallQueue { frequency: 0 };
+                        let insertion =
+                            Insertion::new(*hash, Some(placement)).with_priority(priority);
+                        class.policy.on_insert(class_slot, insertion);
+                    }
+                }
+            }
+            class.capacity = capacity;
+        }
+
+        entries.push((slot, hash));
+        entries.len() - 1
+    }
+
+    fn policies(&self) -> impl Iterator<Item = &dyn EvictionPolicy> {
+        self.classes
+            .iter()
+            .flatten()
+            .map(|class| class.policy.as_ref())
+    }
+}
+
+impl Class {
+    /// Returns the slot of the shard that holds the entry in `class_slot`.
+    fn shard_slot(&self, class_slot: usize) -> usize {
+        match &self.entries {
+            Some(entries) => entries[class_slot].0,
+            None => class_slot,
+        }
+    }
+
+    /// Marks `class_slot` as free and returns the slot of the shard that held its entry.
+    fn free(&mut self, class_slot: usize) -> usize {
+        let Some(entries) = &mut self.entries else {
+            return class_slot;
+        };
+
+        self.free_slots.push(class_slot);
+        mem::replace(&mut entries[class_slot], (UNTRACKED, 0)).0
+    }
+}
+
+/// Creates a policy for `capacity` that receives the ghost hashes and the entries of `old_policy`.
+///
+/// `relocations` maps the slots of the old policy to the slots of the new one and the hashes of
+/// their keys. Returns the new policy and the new slot of every old slot the old policy reported a
+/// placement for.
+fn rebuild(
+    old_policy: Box<dyn EvictionPolicy>,
+    capacity: usize,
+    relocations: &[Option<(usize, u64)>],
+    make_policy: &PolicyFactory,
+    pending_stats: &mut ShardStats,
+) -> (Box<dyn EvictionPolicy>, Vec<Option<usize>>) {
+    old_policy.take_stats(pending_stats);
+
+    let mut policy = make_policy(capacity);
+    for hash in old_policy.ghost_hashes() {
+        policy.restore_ghost(hash);
+    }
+    for hash in old_policy.main_queue_ghost_hashes() {
+        policy.restore_main_queue_ghost(hash);
+    }
+
+    let mut restored = vec![None; relocations.len()];
+    for (old_slot, placement) in old_policy.placements() {
+        let Some((new_slot, hash)) = relocations.get(old_slot).copied().flatten() else {
+            continue;
+        };
+
+        if restored[old_slot].replace(new_slot).is_none() {
+            policy.on_insert(new_slot, Insertion::new(hash, Some(placement)));
+        }
+    }
+
+    (policy, restored)
+}
+
+impl EvictionPolicy for Prioritized {
+    fn on_insert(&mut self, slot: usize, insertion: Insertion) {
+        let priority = insertion.priority;
+        self.class_mut(priority);
+        let class_slot = self.allocate_class_slot(priority, slot, insertion.hash);
+        self.slots[slot] = SlotClass {
+            priority,
+            class_slot,
+        };
+
+        if let Some(class) = &mut self.classes[priority.index()] {
+            class.policy.on_insert(class_slot, insertion);
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) -> bool {
+        self.capacity = capacity;
+        self.slots.resize(capacity, SlotClass::default());
+
+        // only the default class tracks the slots of the shard
+        match &mut self.classes[Priority::default().index()] {
+            Some(class) => {
+                class.capacity = capacity;
+                class.policy.set_capacity(capacity)
+            }
+            None => true,
+        }
+    }
+
+    fn on_access(&self, slot: usize) {
+        let SlotClass {
+            priority,
+            class_slot,
+        } = self.slots[slot];
+        if class_slot == UNTRACKED {
+            return;
+        }
+
+        if let Some(class) = &self.classes[priority.index()] {
+            class.policy.on_access(class_slot);
+        }
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        for class in self.classes.iter_mut().flatten() {
+            if let Some(class_slot) = class.policy.evict() {
+                let slot = class.free(class_slot);
+                self.slots[slot].class_slot = UNTRACKED;
+                return Some(slot);
+            }
+        }
+
+        None
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        let SlotClass {
+            priority,
+            class_slot,
+        } = self.slots[slot];
+        if class_slot == UNTRACKED {
+            return;
+        }
+
+        if let Some(class) = &mut self.classes[priority.index()] {
+            class.policy.on_remove(class_slot);
+            class.free(class_slot);
+        }
+        self.slots[slot].class_slot = UNTRACKED;
+    }
+
+    fn placements(&self) -> Vec<(usize, Placement)> {
+        self.classes
+            .iter()
+            .flatten()
+            .flat_map(|class| {
+                class
+                    .policy
+                    .placements()
+                    .into_iter()
+                    .map(|(class_slot, placement)| (class.shard_slot(class_slot), placement))
+            })
+            .collect()
+    }
+
+    fn take_stats(&self, stats: &mut ShardStats) {
+        stats.add_counts(&mem::take(&mut *self.pending_stats.lock()));
+
+        for policy in self.policies() {
+            policy.take_stats(stats);
+        }
+    }
+}
+
+impl Debug for Prioritized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let classes: Vec<_> = self
+            .classes
+            .iter()
+            .map(|class| class.as_ref().map(|class| (&class.policy, class.capacity)))
+            .collect();
+        f.debug_struct("Prioritized")
+            .field("capacity", &self.capacity)
+            .field("classes", &classes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::S3Fifo;
+
+    fn prioritized(capacity: usize) -> Prioritized {
+        Prioritized::new(
+            capacity,
+            Arc::new(|capacity| Box::new(S3Fifo::with_capacity(capacity))),
+        )
+    }
+
+    fn insert(policy: &mut Prioritized, slot: usize, priority: Priority) {
+        let insertion = Insertion::new(slot as u64, None).with_priority(priority);
+        policy.on_insert(slot, insertion);
+    }
+
+    #[test]
+    fn it_evicts_lower_classes_first() {
+        // given
+        let mut policy = prioritized(3);
+        insert(&mut policy, 0, Priority::High);
+        insert(&mut policy, 1, Priority::Low);
+        insert(&mut policy, 2, Priority::Normal);
+
+        // when
+        let evicted = (
+            policy.evict(),
+            policy.evict(),
+            policy.evict(),
+            policy.evict(),
+        );
+
+        // then
+        assert_eq!(evicted, (Some(1), Some(2), Some(0), None));
+    }
+
+    #[test]
+    fn it_routes_removals_to_the_class_of_the_slot() {
+        // given
+        let mut policy = prioritized(2);
+        insert(&mut policy, 0, Priority::Low);
+        insert(&mut policy, 1, Priority::High);
+
+        // when
+        policy.on_remove(0);
+
+        // then
+        assert_eq!(policy.evict(), Some(1));
+        assert_eq!(policy.evict(), None);
+    }
+
+    #[test]
+    fn it_relocates_entries_when_resizing() {
+        // given
+        let mut policy = prioritized(4);
+        insert(&mut policy, 0, Priority::Low);
+        insert(&mut policy, 1, Priority::High);
+        insert(&mut policy, 3, Priority::Normal);
+
+        // when
+        policy.resize(2, &[None, Some((1, 1)), None, Some((0, 3))]);
+
+        // then
+        assert_eq!(
+            (policy.priority_of(0), policy.priority_of(1)),
+            (Priority::Normal, Priority::High)
+        );
+        assert_eq!(
+            (policy.evict(), policy.evict(), policy.evict()),
+            (Some(0), Some(1), None)
+        );
+    }
+
+    #[test]
+    fn it_creates_policies_on_first_use() {
+        // given
+        let mut policy = prioritized(2);
+
+        // when
+        insert(&mut policy, 0, Priority::High);
+
+        // then
+        assert_eq!(policy.policies().count(), 1);
+    }
+
+    #[test]
+    fn it_creates_small_policies_for_other_classes() {
+        // given
+        let mut policy = prioritized(1000);
+
+        // when
+        insert(&mut policy, 500, Priority::High);
+
+        // then
+        let high = policy.classes[Priority::High.index()].as_ref().unwrap();
+        assert_eq!(high.capacity, INITIAL_CLASS_CAPACITY);
+    }
+
+    #[test]
+    fn it_grows_the_policies_of_other_classes_with_their_entries() {
+        // given
+        let mut policy = prioritized(100);
+        let slots: Vec<_> = (0..INITIAL_CLASS_CAPACITY + 1).map(|i| i * 5).collect();
+
+        // when
+        for slot in &slots {
+            insert(&mut policy, *slot, Priority::Low);
+        }
+
+        // then
+        let low = policy.classes[Priority::Low.index()].as_ref().unwrap();
+        assert_eq!(low.capacity, INITIAL_CLASS_CAPACITY * 2);
+        let mut evicted: Vec<_> = std::iter::from_fn(|| policy.evict()).collect();
+        evicted.sort();
+        assert_eq!(evicted, slots);
+    }
+
+    #[test]
+    fn it_reuses_the_slots_of_removed_entries_of_other_classes() {
+        // given
+        let mut policy = prioritized(100);
+        insert(&mut policy, 10, Priority::High);
+        insert(&mut policy, 20, Priority::High);
+
+        // when
+        policy.on_remove(10);
+        insert(&mut policy, 30, Priority::High);
+
+        // then
+        let high = policy.classes[Priority::High.index()].as_ref().unwrap();
+        assert_eq!(high.entries.as_ref().unwrap().len(), 2);
+        let mut evicted: Vec<_> = std::iter::from_fn(|| policy.evict()).collect();
+        evicted.sort();
+        assert_eq!(evicted, vec![20, 30]);
+    }
+}
@@ -0,0 +1,893 @@
+use crate::cache::fingerprint_queue::FingerprintQueue;
+use crate::cache::fixed_size_hash_table::FixedSizeHashTable;
+use crate::cache::policy::tiny_lfu::TinyLfu;
+use crate::cache::policy::{EvictionPolicy, Insertion};
+use crate::cache::slot_list::SlotList;
+use crate::{Placement, ShardStats};
+use std::cmp;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+const MAX_NUM_ACCESSED: u8 = 3;
+const MAX_REINSERTIONS_PER_EVICTION: usize = 32;
+
+/// The S3-FIFO eviction policy as specified in
+/// [FIFO Queues are All You Need for Cache Eviction](https://dl.acm.org/doi/pdf/10.1145/3600006.3613147).
+///
+/// New entries are inserted into a small FIFO queue that holds about 10% of the entries. Entries
+/// that are accessed more than once while in the small queue are promoted to the main queue, all
+/// others are evicted and the hashes of their keys are remembered in a ghost queue. Entries whose
+/// keys are found in the ghost queue are inserted directly into the main queue. Entries in the main
+/// queue that were accessed since they were last considered for eviction are given another round.
+/// To bound the work of a single eviction, at most 32 entries are promoted from the small queue or
+/// given another round in the main queue per eviction. Once the budget is spent, the next entry
+/// of the main queue is evicted even if it was accessed, while the entries that were moved have
+/// aged, so later evictions find unaccessed entries sooner.
+///
+/// Replaced and pinned entries are removed from the middle of their queue, so both queues are
+/// linked lists over the slots of the shard and removals leave no holes behind.
+///
+/// Optionally, a TinyLFU admission filter (see [`crate::Admission::TinyLfu`]) protects the main
+/// queue: once it is full, entries only enter it if their keys were accessed more often than the
+/// key of the entry that would be evicted from it next.
+///
+/// Optionally, the hashes of keys evicted from the main queue are remembered in a second ghost
+/// queue (see [`crate::Config::remember_main_queue_evictions`]).
+///
+/// Optionally, the split between the queues adapts to the workload (see
+/// [`crate::Config::adaptive_small_queue`]).
+///
+/// When slots are added to or removed from the end of the shard, the queues keep their entries.
+/// The ghost queues grow with the capacity and keep their hashes, but never shrink, while the
+/// TinyLFU filter keeps the size it was created with.
+///
+/// This is the default policy of the cache.
+#[derive(Debug)]
+pub struct S3Fifo {
+    slots: Vec<SlotState>,
+    small_queue: SlotList,
+    main_queue: SlotList,
+    ghost_queue: GhostQueue,
+    main_ghost_queue: Option<GhostQueue>,
+    ghost_queue_capacity: usize,
+    small_queue_len: usize,
+    main_queue_len: usize,
+    small_queue_target: usize,
+    main_queue_target: usize,
+    tiny_lfu: Option<TinyLfu>,
+    adaptive: bool,
+    ghost_hit_count: AtomicU64,
+    main_queue_ghost_hit_count: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct SlotState {
+    hash: u64,
+    queue: Queue,
+    num_accessed: AtomicU8,
+}
+
+#[derive(Debug)]
+enum GhostQueue {
+    DirectMapped(FixedSizeHashTable<u64>),
+    Fifo(FingerprintQueue),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum Queue {
+    #[default]
+    None,
+    Small,
+    Main,
+}
+
+impl S3Fifo {
+    /// Creates the policy for a shard with the specified capacity.
+    pub fn with_capacity(capacity: usize) -> S3Fifo {
+        let small_queue_target = cmp::max(capacity / 10, 1);
+        let main_queue_target = cmp::max(capacity.saturating_sub(small_queue_target), 1);
+
+        let mut slots = Vec::with_capacity(capacity);
+        slots.resize_with(capacity, SlotState::default);
+
+        S3Fifo {
+            slots,
+            small_queue: SlotList::with_capacity(capacity),
+            main_queue: SlotList::with_capacity(capacity),
+            ghost_queue: GhostQueue::DirectMapped(FixedSizeHashTable::with_capacity(
+                main_queue_target,
+            )),
+            main_ghost_queue: None,
+            ghost_queue_capacity: main_queue_target,
+            small_queue_len: 0,
+            main_queue_len: 0,
+            small_queue_target,
+            main_queue_target,
+            tiny_lfu: None,
+            adaptive: false,
+            ghost_hit_count: AtomicU64::new(0),
+            main_queue_ghost_hit_count: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates the policy for a shard with the specified capacity, with a TinyLFU admission
+    /// filter in front of the main queue.
+    pub fn with_tiny_lfu(capacity: usize) -> S3Fifo {
+        S3Fifo {
+            tiny_lfu: Some(TinyLfu::with_capacity(capacity)),
+            ..S3Fifo::with_capacity(capacity)
+        }
+    }
+
+    /// Enables the adaptive sizing of the small queue.
+    ///
+    /// Similar to ARC, the target size of the small queue starts at 10% of the capacity and grows
+    /// by one entry for every ghost hit, since those entries were evicted from the small queue too
+    /// early. It shrinks by one entry for every promotion into a full main queue, since those
+    /// entries proved to be frequently accessed and need space in the main queue. The small queue
+    /// holds at least one entry and at most half of the capacity.
+    pub fn adaptive(self) -> S3Fifo {
+        S3Fifo {
+            adaptive: true,
+            ..self
+        }
+    }
+
+    /// Replaces the direct-mapped ghost queue with a FIFO queue of 32-bit key fingerprints.
+    ///
+    /// The direct-mapped ghost queue forgets a hash as soon as another hash maps to the same
+    /// bucket. The FIFO ghost queue forgets hashes in the order in which they were added, as
+    /// specified in the S3-FIFO paper, at the cost of a small fingerprint map.
+    pub fn fifo_ghost_queue(self) -> S3Fifo {
+        let remember_main_queue_evictions = self.main_ghost_queue.is_some();
+
+        let policy = S3Fifo {
+            ghost_queue: GhostQueue::Fifo(FingerprintQueue::with_capacity(self.main_queue_target)),
+            ..self
+        };
+
+        if remember_main_queue_evictions {
+            policy.remember_main_queue_evictions()
+        } else {
+            policy
+        }
+    }
+
+    /// Also remembers the hashes of keys evicted from the main queue, in a second ghost queue of
+    /// the same kind and size as the first one.
+    ///
+    /// Entries whose keys are found in either ghost queue are inserted directly into the main
+    /// queue, so keys that were frequently accessed before their eviction skip the small queue
+    /// when they come back. Hits in the second ghost queue are reported as
+    /// [`ShardStats::main_queue_ghost_hit_count`]. With adaptive sizing, they shrink the small
+    /// queue.
+    pub fn remember_main_queue_evictions(self) -> S3Fifo {
+        let capacity = self.main_queue_target;
+        let main_ghost_queue = match self.ghost_queue {
+            GhostQueue::DirectMapped(_) => {
+                GhostQueue::DirectMapped(FixedSizeHashTable::with_capacity(capacity))
+            }
+            GhostQueue::Fifo(_) => GhostQueue::Fifo(FingerprintQueue::with_capacity(capacity)),
+        };
+
+        S3Fifo {
+            main_ghost_queue: Some(main_ghost_queue),
+            ..self
+        }
+    }
+
+    fn grow_small_queue(&mut self) {
+        let max_small_queue_target = cmp::max(self.slots.len() / 2, 1);
+        self.resize_small_queue(cmp::min(
+            self.small_queue_target + 1,
+            max_small_queue_target,
+        ));
+    }
+
+    fn shrink_small_queue(&mut self) {
+        self.resize_small_queue(cmp::max(self.small_queue_target - 1, 1));
+    }
+
+    fn resize_small_queue(&mut self, small_queue_target: usize) {
+        self.small_queue_target = small_queue_target;
+        self.main_queue_target = cmp::max(self.slots.len().saturating_sub(small_queue_target), 1);
+    }
+
+    /// Returns whether the key with the specified hash may enter the main queue.
+    fn admits_into_main_queue(&self, hash: u64) -> bool {
+        let Some(tiny_lfu) = &self.tiny_lfu else {
+            return true;
+        };
+
+        if self.main_queue_len < self.main_queue_target {
+            return true;
+        }
+
+        let Some(victim) = self.main_queue.front() else {
+            return true;
+        };
+
+        tiny_lfu.estimate(hash) > tiny_lfu.estimate(self.slots[victim].hash)
+    }
+
+    fn push_small_queue(&mut self, slot: usize, num_accessed: u8) {
+        self.small_queue.push_back(slot);
+        self.small_queue_len += 1;
+
+        let state = &mut self.slots[slot];
+        state.queue = Queue::Small;
+        state.set_num_accessed(num_accessed);
+    }
+
+    fn push_main_queue(&mut self, slot: usize, num_accessed: u8) {
+        self.main_queue.push_back(slot);
+        self.main_queue_len += 1;
+
+        let state = &mut self.slots[slot];
+        state.queue = Queue::Main;
+        state.set_num_accessed(num_accessed);
+    }
+
+    /// Evicts the first entry of the small queue that is not promoted to the main queue. Every
+    /// promotion counts towards `reinsertions`. Returns [`None`] once
+    /// [`MAX_REINSERTIONS_PER_EVICTION`] is reached, so the main queue evicts instead.
+    fn evict_small_queue(&mut self, reinsertions: &mut usize) -> Option<usize> {
+        while *reinsertions < MAX_REINSERTIONS_PER_EVICTION
+            && let Some(slot) = self.small_queue.pop_front()
+        {
+            self.small_queue_len -= 1;
+
+            let hash = self.slots[slot].hash;
+
+            if self.slots[slot].get_num_accessed() > 1 && self.admits_into_main_queue(hash) {
+                if self.adaptive && self.main_queue_len >= self.main_queue_target {
+                    self.shrink_small_queue();
+                }
+
+                *reinsertions += 1;
+
+                // move the entry to the main queue and reset the access counter
+                self.push_main_queue(slot, 0);
+                continue;
+            }
+
+            // evict the entry and add the hash of its key to the ghost queue
+            self.ghost_queue.insert(hash);
+            self.forget(slot);
+            return Some(slot);
+        }
+
+        None
+    }
+
+    /// Evicts the first entry of the main queue that is not given another round. Every
+    /// reinsertion counts towards `reinsertions`, which already includes the promotions from the
+    /// small queue.
+    fn evict_main_queue(&mut self, reinsertions: &mut usize) -> Option<usize> {
+        while let Some(slot) = self.main_queue.pop_front() {
+            self.main_queue_len -= 1;
+
+            let num_accessed = self.slots[slot].get_num_accessed();
+            if num_accessed > 0 && *reinsertions < MAX_REINSERTIONS_PER_EVICTION {
+                *reinsertions += 1;
+                self.push_main_queue(slot, num_accessed - 1);
+                continue;
+            }
+
+            if let Some(main_ghost_queue) = &mut self.main_ghost_queue {
+                main_ghost_queue.insert(self.slots[slot].hash);
+            }
+
+            self.forget(slot);
+            return Some(slot);
+        }
+
+        None
+    }
+
+    fn forget(&mut self, slot: usize) {
+        let state = &mut self.slots[slot];
+        state.queue = Queue::None;
+        state.set_num_accessed(0);
+    }
+}
+
+impl EvictionPolicy for S3Fifo {
+    fn on_insert(&mut self, slot: usize, insertion: Insertion) {
+        self.slots[slot].hash = insertion.hash;
+
+        if let Some(tiny_lfu) = &mut self.tiny_lfu {
+            tiny_lfu.record(insertion.hash);
+            tiny_lfu.age_if_needed();
+        }
+
+        match insertion.placement {
+            Some(Placement::SmallQueue { frequency }) => self.push_small_queue(slot, frequency),
+            Some(Placement::MainQueue { frequency }) => self.push_main_queue(slot, frequency),
+            None => {
+                let main_queue_ghost_hit = self
+                    .main_ghost_queue
+                    .as_ref()
+                    .is_some_and(|ghost_queue| ghost_queue.contains(insertion.hash));
+                let ghost_hit = main_queue_ghost_hit || self.ghost_queue.contains(insertion.hash);
+
+                if main_queue_ghost_hit {
+                    *self.main_queue_ghost_hit_count.get_mut() += 1;
+                }
+
+                if ghost_hit {
+                    *self.ghost_hit_count.get_mut() += 1;
+                }
+
+                if self.adaptive && main_queue_ghost_hit {
+                    self.shrink_small_queue();
+                } else if self.adaptive && ghost_hit {
+                    self.grow_small_queue();
+                }
+
+                if ghost_hit && self.admits_into_main_queue(insertion.hash) {
+                    self.push_main_queue(slot, 0);
+                } else {
+                    self.push_small_queue(slot, 0);
+                }
+            }
+        }
+    }
+
+    fn on_access(&self, slot: usize) {
+        let state = &self.slots[slot];
+        state.increment_num_accessed();
+
+        if let Some(tiny_lfu) = &self.tiny_lfu {
+            tiny_lfu.record(state.hash);
+        }
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        let prefer_small_queue =
+            self.small_queue_len >= self.small_queue_target || self.main_queue_len == 0;
+
+        // promotions and reinsertions share one budget
+        let mut reinsertions = 0;
+        if prefer_small_queue && let Some(slot) = self.evict_small_queue(&mut reinsertions) {
+            return Some(slot);
+        }
+
+        self.evict_main_queue(&mut reinsertions)
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        let state = &self.slots[slot];
+        match state.queue {
+            Queue::None => return,
+            Queue::Small => {
+                self.small_queue.remove(slot);
+                self.small_queue_len -= 1;
+            }
+            Queue::Main => {
+                self.main_queue.remove(slot);
+                self.main_queue_len -= 1;
+            }
+        }
+
+        self.forget(slot);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) -> bool {
+        self.slots.resize_with(capacity, SlotState::default);
+        self.small_queue.set_capacity(capacity);
+        self.main_queue.set_capacity(capacity);
+
+        let small_queue_target = if self.adaptive {
+            let max_small_queue_target = cmp::max(capacity / 2, 1);
+            cmp::min(self.small_queue_target, max_small_queue_target)
+        } else {
+            cmp::max(capacity / 10, 1)
+        };
+        self.resize_small_queue(small_queue_target);
+
+        let ghost_queue_capacity = cmp::max(capacity.saturating_sub(cmp::max(capacity / 10, 1)), 1);
+        if ghost_queue_capacity > self.ghost_queue_capacity {
+            self.ghost_queue.grow(ghost_queue_capacity);
+            if let Some(main_ghost_queue) = &mut self.main_ghost_queue {
+                main_ghost_queue.grow(ghost_queue_capacity);
+            }
+            self.ghost_queue_capacity = ghost_queue_capacity;
+        }
+
+        true
+    }
+
+    fn placements(&self) -> Vec<(usize, Placement)> {
+        let main_queue = self.main_queue.iter().map(|slot| {
+            let frequency = self.slots[slot].get_num_accessed();
+            (slot, Placement::MainQueue { frequency })
+        });
+
+        let small_queue = self.small_queue.iter().map(|slot| {
+            let frequency = self.slots[slot].get_num_accessed();
+            (slot, Placement::SmallQueue { frequency })
+        });
+
+        main_queue.chain(small_queue).collect()
+    }
+
+    fn ghost_hashes(&self) -> Vec<u64> {
+        self.ghost_queue.hashes()
+    }
+
+    fn main_queue_ghost_hashes(&self) -> Vec<u64> {
+        self.main_ghost_queue
+            .as_ref()
+            .map_or_else(Vec::new, GhostQueue::hashes)
+    }
+
+    fn restore_ghost(&mut self, hash: u64) {
+        self.ghost_queue.insert(hash);
+    }
+
+    fn restore_main_queue_ghost(&mut self, hash: u64) {
+        if let Some(main_ghost_queue) = &mut self.main_ghost_queue {
+            main_ghost_queue.insert(hash);
+        }
+    }
+
+    fn take_stats(&self, stats: &mut ShardStats) {
+        stats.ghost_hit_count += self.ghost_hit_count.swap(0, Ordering::AcqRel);
+        stats.main_queue_ghost_hit_count +=
+            self.main_queue_ghost_hit_count.swap(0, Ordering::AcqRel);
+    }
+}
+
+impl GhostQueue {
+    fn insert(&mut self, hash: u64) {
+        match self {
+            GhostQueue::DirectMapped(table) => table.insert(hash, hash),
+            GhostQueue::Fifo(queue) => queue.insert(hash),
+        }
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        match self {
+            GhostQueue::DirectMapped(table) => table.contains(hash, &hash),
+            GhostQueue::Fifo(queue) => queue.contains(hash),
+        }
+    }
+
+    fn hashes(&self) -> Vec<u64> {
+        match self {
+            GhostQueue::DirectMapped(table) => table.iter().copied().collect(),
+            GhostQueue::Fifo(queue) => queue.iter().collect(),
+        }
+    }
+
+    /// Replaces the queue with a queue of the same kind and the specified capacity, which
+    /// remembers the same hashes.
+    fn grow(&mut self, capacity: usize) {
+        let mut grown = match self {
+            GhostQueue::DirectMapped(_) => {
+                GhostQueue::DirectMapped(FixedSizeHashTable::with_capacity(capacity))
+            }
+            GhostQueue::Fifo(_) => GhostQueue::Fifo(FingerprintQueue::with_capacity(capacity)),
+        };
+        for hash in self.hashes() {
+            grown.insert(hash);
+        }
+        *self = grown;
+    }
+}
+
+impl SlotState {
+    fn set_num_accessed(&self, val: u8) {
+        self.num_accessed
+            .store(cmp::min(val, MAX_NUM_ACCESSED), Ordering::Release);
+    }
+
+    fn get_num_accessed(&self) -> u8 {
+        self.num_accessed.load(Ordering::Acquire)
+    }
+
+    fn increment_num_accessed(&self) {
+        let _ =
+            self.num_accessed
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current_val| {
+                    (current_val < MAX_NUM_ACCESSED).then_some(current_val + 1)
+                });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(policy: &mut S3Fifo, slot: usize, hash: u64) {
+        policy.on_insert(slot, Insertion::new(hash, None));
+    }
+
+    #[test]
+    fn it_evicts_unaccessed_entries_from_the_small_queue_first() {
+        // given
+        let mut policy = S3Fifo::with_capacity(10);
+        for slot in 0..10 {
+            insert(&mut policy, slot, slot as u64);
+        }
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(0));
+    }
+
+    #[test]
+    fn it_promotes_entries_accessed_more_than_once() {
+        // given
+        let mut policy = S3Fifo::with_capacity(10);
+        for slot in 0..10 {
+            insert(&mut policy, slot, slot as u64);
+        }
+        policy.on_access(0);
+        policy.on_access(0);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(1));
+        assert_eq!(policy.slots[0].queue, Queue::Main);
+    }
+
+    #[test]
+    fn it_inserts_ghost_hits_into_the_main_queue() {
+        // given
+        let mut policy = S3Fifo::with_capacity(10);
+        for slot in 0..10 {
+            insert(&mut policy, slot, slot as u64);
+        }
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 0);
+
+        // then
+        assert_eq!(policy.slots[evicted].queue, Queue::Main);
+    }
+
+    #[test]
+    fn it_gives_accessed_main_queue_entries_another_round() {
+        // given
+        let mut policy = S3Fifo::with_capacity(3);
+        for slot in 0..3 {
+            let placement = Some(Placement::MainQueue { frequency: 0 });
+            policy.on_insert(slot, Insertion::new(slot as u64, placement));
+        }
+        policy.on_access(0);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(1));
+    }
+
+    #[test]
+    fn it_bounds_the_reinsertions_per_eviction() {
+        // given
+        let mut policy = S3Fifo::with_capacity(100);
+        for slot in 0..100 {
+            let placement = Some(Placement::MainQueue { frequency: 0 });
+            policy.on_insert(slot, Insertion::new(slot as u64, placement));
+            policy.on_access(slot);
+        }
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(MAX_REINSERTIONS_PER_EVICTION));
+        assert_eq!(policy.slots[0].get_num_accessed(), 0);
+        assert_eq!(policy.main_queue_len, 99);
+    }
+
+    #[test]
+    fn it_bounds_the_promotions_per_eviction() {
+        // given
+        let mut policy = S3Fifo::with_capacity(100);
+        for slot in 0..100 {
+            policy.on_insert(slot, Insertion::new(slot as u64, None));
+            policy.on_access(slot);
+            policy.on_access(slot);
+        }
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(0));
+        assert_eq!(policy.main_queue_len, MAX_REINSERTIONS_PER_EVICTION - 1);
+        assert_eq!(policy.small_queue_len, 100 - MAX_REINSERTIONS_PER_EVICTION);
+    }
+
+    #[test]
+    fn it_rejects_ghost_hits_less_frequent_than_the_main_queue_victim() {
+        // given
+        let mut policy = S3Fifo::with_tiny_lfu(2);
+        let placement = Some(Placement::MainQueue { frequency: 0 });
+        policy.on_insert(0, Insertion::new(100, placement));
+        for _ in 0..3 {
+            policy.on_access(0);
+        }
+        insert(&mut policy, 1, 200);
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 200);
+
+        // then
+        assert_eq!(evicted, 1);
+        assert_eq!(policy.slots[evicted].queue, Queue::Small);
+    }
+
+    #[test]
+    fn it_admits_ghost_hits_more_frequent_than_the_main_queue_victim() {
+        // given
+        let mut policy = S3Fifo::with_tiny_lfu(2);
+        let placement = Some(Placement::MainQueue { frequency: 0 });
+        policy.on_insert(0, Insertion::new(100, placement));
+        insert(&mut policy, 1, 200);
+        for _ in 0..3 {
+            policy.on_access(1);
+        }
+        policy.on_remove(1);
+        insert(&mut policy, 1, 200);
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 200);
+
+        // then
+        assert_eq!(evicted, 1);
+        assert_eq!(policy.slots[evicted].queue, Queue::Main);
+    }
+
+    #[test]
+    fn it_grows_the_adaptive_small_queue_on_ghost_hits() {
+        // given
+        let mut policy = S3Fifo::with_capacity(20).adaptive();
+        for slot in 0..20 {
+            insert(&mut policy, slot, slot as u64);
+        }
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 0);
+
+        // then
+        assert_eq!(policy.small_queue_target, 3);
+        assert_eq!(policy.main_queue_target, 17);
+    }
+
+    #[test]
+    fn it_shrinks_the_adaptive_small_queue_on_promotions_into_a_full_main_queue() {
+        // given
+        let mut policy = S3Fifo::with_capacity(20).adaptive();
+        for slot in 0..18 {
+            let placement = Some(Placement::MainQueue { frequency: 0 });
+            policy.on_insert(slot, Insertion::new(slot as u64, placement));
+        }
+        insert(&mut policy, 18, 18);
+        insert(&mut policy, 19, 19);
+        policy.on_access(18);
+        policy.on_access(18);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(19));
+        assert_eq!(policy.small_queue_target, 1);
+        assert_eq!(policy.main_queue_target, 19);
+    }
+
+    #[test]
+    fn it_keeps_the_small_queue_target_without_adaptive_sizing() {
+        // given
+        let mut policy = S3Fifo::with_capacity(20);
+        for slot in 0..20 {
+            insert(&mut policy, slot, slot as u64);
+        }
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 0);
+
+        // then
+        assert_eq!(policy.small_queue_target, 2);
+    }
+
+    #[test]
+    fn it_inserts_ghost_hits_of_the_fifo_ghost_queue_into_the_main_queue() {
+        // given
+        let mut policy = S3Fifo::with_capacity(10).fifo_ghost_queue();
+        for slot in 0..10 {
+            insert(&mut policy, slot, slot as u64);
+        }
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 0);
+
+        // then
+        assert_eq!(policy.slots[evicted].queue, Queue::Main);
+        assert_eq!(policy.ghost_hashes(), vec![0]);
+    }
+
+    #[test]
+    fn it_inserts_main_queue_ghost_hits_into_the_main_queue() {
+        // given
+        let mut policy = S3Fifo::with_capacity(3).remember_main_queue_evictions();
+        for slot in 0..3 {
+            let placement = Some(Placement::MainQueue { frequency: 0 });
+            policy.on_insert(slot, Insertion::new(slot as u64, placement));
+        }
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 0);
+
+        // then
+        let mut stats = ShardStats::default();
+        policy.take_stats(&mut stats);
+        assert_eq!(evicted, 0);
+        assert_eq!(policy.slots[evicted].queue, Queue::Main);
+        assert_eq!(stats.ghost_hit_count, 1);
+        assert_eq!(stats.main_queue_ghost_hit_count, 1);
+    }
+
+    #[test]
+    fn it_keeps_main_queue_evictions_apart_from_other_ghosts() {
+        // given
+        let mut policy = S3Fifo::with_capacity(3).remember_main_queue_evictions();
+        for slot in 0..3 {
+            let placement = Some(Placement::MainQueue { frequency: 0 });
+            policy.on_insert(slot, Insertion::new(slot as u64, placement));
+        }
+        policy.evict();
+
+        // when
+        let mut restored = S3Fifo::with_capacity(3).remember_main_queue_evictions();
+        for hash in policy.main_queue_ghost_hashes() {
+            restored.restore_main_queue_ghost(hash);
+        }
+        insert(&mut restored, 0, 0);
+
+        // then
+        let mut stats = ShardStats::default();
+        restored.take_stats(&mut stats);
+        assert!(policy.ghost_hashes().is_empty());
+        assert_eq!(stats.main_queue_ghost_hit_count, 1);
+    }
+
+    #[test]
+    fn it_forgets_main_queue_evictions_by_default() {
+        // given
+        let mut policy = S3Fifo::with_capacity(3);
+        for slot in 0..3 {
+            let placement = Some(Placement::MainQueue { frequency: 0 });
+            policy.on_insert(slot, Insertion::new(slot as u64, placement));
+        }
+        let evicted = policy.evict().unwrap();
+
+        // when
+        insert(&mut policy, evicted, 0);
+
+        // then
+        let mut stats = ShardStats::default();
+        policy.take_stats(&mut stats);
+        assert_eq!(policy.slots[evicted].queue, Queue::Small);
+        assert_eq!(stats.ghost_hit_count, 0);
+    }
+
+    #[test]
+    fn it_resets_ghost_hit_counts_when_taking_stats() {
+        // given
+        let mut policy = S3Fifo::with_capacity(10);
+        for slot in 0..10 {
+            insert(&mut policy, slot, slot as u64);
+        }
+        let evicted = policy.evict().unwrap();
+        insert(&mut policy, evicted, 0);
+
+        // when
+        let mut first = ShardStats::default();
+        policy.take_stats(&mut first);
+        let mut second = ShardStats::default();
+        policy.take_stats(&mut second);
+
+        // then
+        assert_eq!(first.ghost_hit_count, 1);
+        assert_eq!(first.main_queue_ghost_hit_count, 0);
+        assert_eq!(second.ghost_hit_count, 0);
+    }
+
+    #[test]
+    fn it_keeps_its_queues_when_the_capacity_changes() {
+        // given
+        let mut policy = S3Fifo::with_capacity(10);
+        for slot in 0..5 {
+            insert(&mut policy, slot, slot as u64);
+        }
+        policy.on_access(0);
+        policy.on_access(0);
+
+        // when
+        let resized = policy.set_capacity(20);
+        for slot in 5..20 {
+            insert(&mut policy, slot, slot as u64);
+        }
+
+        // then
+        assert!(resized);
+        assert_eq!(policy.small_queue_target, 2);
+        assert_eq!(policy.evict(), Some(1));
+        assert_eq!(policy.slots[0].queue, Queue::Main);
+    }
+
+    #[test]
+    fn it_grows_its_ghost_queues_with_the_capacity() {
+        // given
+        let mut policy = S3Fifo::with_capacity(2)
+            .fifo_ghost_queue()
+            .remember_main_queue_evictions();
+        policy.restore_ghost(7);
+        policy.restore_main_queue_ghost(8);
+
+        // when
+        policy.set_capacity(20);
+        for hash in 100..117 {
+            policy.restore_ghost(hash);
+        }
+
+        // then
+        assert_eq!(policy.ghost_queue_capacity, 18);
+        assert!(policy.ghost_hashes().contains(&7));
+        assert_eq!(policy.main_queue_ghost_hashes(), vec![8]);
+    }
+
+    #[test]
+    fn it_keeps_the_order_of_the_queue_when_removing_entries() {
+        // given
+        let mut policy = S3Fifo::with_capacity(4);
+        for slot in 0..4 {
+            let placement = Some(Placement::MainQueue { frequency: 0 });
+            policy.on_insert(slot, Insertion::new(slot as u64, placement));
+        }
+
+        // when
+        policy.on_remove(1);
+        insert(&mut policy, 1, 1);
+
+        // then
+        assert_eq!(policy.main_queue.iter().collect::<Vec<_>>(), vec![0, 2, 3]);
+        assert_eq!(policy.small_queue.iter().collect::<Vec<_>>(), vec![1]);
+        assert_eq!((policy.main_queue_len, policy.small_queue_len), (3, 1));
+    }
+
+    #[test]
+    fn it_reuses_space_of_removed_entries() {
+        // given
+        let mut policy = S3Fifo::with_capacity(2);
+        insert(&mut policy, 0, 0);
+        insert(&mut policy, 1, 1);
+
+        // when
+        for _ in 0..10 {
+            policy.on_remove(1);
+            insert(&mut policy, 1, 1);
+        }
+
+        // then
+        assert_eq!(policy.evict(), Some(0));
+        assert_eq!(policy.evict(), Some(1));
+        assert_eq!(policy.evict(), None);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::Placement;
+use crate::cache::policy::{EvictionPolicy, Insertion};
+use crate::cache::slot_list::SlotList;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The SIEVE eviction policy as specified in
+/// [SIEVE is Simpler than LRU](https://www.usenix.org/conference/nsdi24/presentation/zhang-yazhuo).
+///
+/// All entries are kept in a single FIFO queue and carry a visited bit that is set when they are
+/// accessed. A hand moves from the oldest to the newest entry, clearing visited bits, and evicts
+/// the first entry that was not visited. Unlike S3-FIFO, entries that survive the hand are not
+/// moved, and there is no ghost queue, so SIEVE needs less memory per entry.
+///
+/// Evicted entries are removed from the middle of the queue, so the queue is a linked list over
+/// the slots of the shard instead of a ring buffer.
+#[derive(Debug)]
+pub struct Sieve {
+    queue: SlotList,
+    visited: Vec<AtomicBool>,
+    hand: Option<usize>,
+}
+
+impl Sieve {
+    /// Creates the policy for a shard with the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Sieve {
+        let mut visited = Vec::with_capacity(capacity);
+        visited.resize_with(capacity, AtomicBool::default);
+
+        Sieve {
+            queue: SlotList::with_capacity(capacity),
+            visited,
+            hand: None,
+        }
+    }
+}
+
+impl EvictionPolicy for Sieve {
+    fn on_insert(&mut self, slot: usize, insertion: Insertion) {
+        let visited = match insertion.placement {
+            Some(Placement::SmallQueue { frequency } | Placement::MainQueue { frequency }) => {
+                frequency > 0
+            }
+            None => false,
+        };
+
+        self.visited[slot].store(visited, Ordering::Release);
+        self.queue.push_back(slot);
+    }
+
+    fn on_access(&self, slot: usize) {
+        self.visited[slot].store(true, Ordering::Release);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        let mut hand = self.hand.or_else(|| self.queue.front())?;
+
+        while self.visited[hand].swap(false, Ordering::AcqRel) {
+            hand = self
+                .queue
+                .next(hand)
+                .or_else(|| self.queue.front())
+                .expect("the queue contains at least the current slot");
+        }
+
+        self.hand = self.queue.next(hand);
+        self.queue.remove(hand);
+
+        Some(hand)
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        if self.hand == Some(slot) {
+            self.hand = self.queue.next(slot);
+        }
+
+        self.queue.remove(slot);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) -> bool {
+        self.visited.resize_with(capacity, AtomicBool::default);
+        self.queue.set_capacity(capacity);
+        true
+    }
+
+    fn placements(&self) -> Vec<(usize, Placement)> {
+        self.queue
+            .iter()
+            .map(|slot| {
+                let frequency = u8::from(self.visited[slot].load(Ordering::Acquire));
+                (slot, Placement::MainQueue { frequency })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(policy: &mut Sieve, slot: usize) {
+        policy.on_insert(slot, Insertion::new(slot as u64, None));
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_unvisited_entry() {
+        // given
+        let mut policy = Sieve::with_capacity(3);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        insert(&mut policy, 2);
+        policy.on_access(0);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(1));
+    }
+
+    #[test]
+    fn it_keeps_the_position_of_visited_entries() {
+        // given
+        let mut policy = Sieve::with_capacity(3);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        insert(&mut policy, 2);
+        policy.on_access(0);
+        policy.evict();
+        insert(&mut policy, 1);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(2));
+        assert_eq!(policy.queue.iter().collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn it_wraps_the_hand_around() {
+        // given
+        let mut policy = Sieve::with_capacity(2);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        policy.on_access(0);
+        policy.on_access(1);
+
+        // when
+        let evicted = policy.evict();
+
+        // then
+        assert_eq!(evicted, Some(0));
+    }
+
+    #[test]
+    fn it_moves_the_hand_when_its_slot_is_removed() {
+        // given
+        let mut policy = Sieve::with_capacity(3);
+        insert(&mut policy, 0);
+        insert(&mut policy, 1);
+        insert(&mut policy, 2);
+        policy.on_access(0);
+        policy.evict();
+        assert_eq!(policy.hand, Some(2));
+
+        // when
+        policy.on_remove(2);
+
+        // then
+        assert_eq!(policy.hand, None);
+        assert_eq!(policy.evict(), Some(0));
+    }
+}
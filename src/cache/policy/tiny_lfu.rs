@@ -0,0 +1,209 @@
+use crate::cache::striped::{STRIPE_COUNT, Striped};
+use std::cmp;
+use std::sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering};
+
+const DEPTH: usize = 4;
+const SEEDS: [u64; DEPTH] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0x85eb_ca77_c2b2_ae63,
+];
+const DOORKEEPER_SEEDS: [u64; 2] = [0xff51_afd7_ed55_8ccd, 0xc4ce_b9fe_1a85_ec53];
+const MAX_COUNT: u8 = 15;
+const SAMPLE_SIZE_FACTOR: usize = 10;
+
+/// The TinyLFU admission filter as specified in
+/// [TinyLFU: A Highly Efficient Cache Admission Policy](https://arxiv.org/abs/1512.00727).
+///
+/// Access frequencies of key hashes are estimated with a count-min sketch of 4-bit counters. The
+/// first access of a hash is only recorded in a doorkeeper bloom filter, so one-shot keys do not
+/// pollute the sketch. After a number of recorded accesses proportional to the capacity, all
+/// counters are halved and the doorkeeper is cleared, so old frequencies fade out. Accesses are
+/// counted per stripe, since every read of the shard records one.
+#[derive(Debug)]
+pub(crate) struct TinyLfu {
+    sketch: Vec<AtomicU8>,
+    doorkeeper: Vec<AtomicU64>,
+    width_mask: usize,
+    doorkeeper_mask: usize,
+    additions: Striped<AtomicUsize>,
+    sample_size: usize,
+}
+
+impl TinyLfu {
+    pub(crate) fn with_capacity(capacity: usize) -> TinyLfu {
+        let width = cmp::max(capacity, 16).next_power_of_two();
+        let doorkeeper_bits = width * 4;
+
+        let mut sketch = Vec::with_capacity(width * DEPTH);
+        sketch.resize_with(width * DEPTH, AtomicU8::default);
+
+        let mut doorkeeper = Vec::with_capacity(doorkeeper_bits / 64);
+        doorkeeper.resize_with(doorkeeper_bits / 64, AtomicU64::default);
+
+        TinyLfu {
+            sketch,
+            doorkeeper,
+            width_mask: width - 1,
+            doorkeeper_mask: doorkeeper_bits - 1,
+            additions: Striped::new(STRIPE_COUNT),
+            sample_size: cmp::max(capacity, 1) * SAMPLE_SIZE_FACTOR,
+        }
+    }
+
+    /// Records an access of the key with the specified hash.
+    pub(crate) fn record(&self, hash: u64) {
+        if self.set_doorkeeper(hash) {
+            for row in 0..DEPTH {
+                let _ = self.sketch[self.index(row, hash)].fetch_update(
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                    |count| (count < MAX_COUNT).then_some(count + 1),
+                );
+            }
+        }
+
+        self.additions.get().fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the estimated number of accesses of the key with the specified hash.
+    pub(crate) fn estimate(&self, hash: u64) -> u8 {
+        let count = (0..DEPTH)
+            .map(|row| self.sketch[self.index(row, hash)].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0);
+
+        count + u8::from(self.doorkeeper_contains(hash))
+    }
+
+    /// Halves all counters and clears the doorkeeper once enough accesses have been recorded.
+    pub(crate) fn age_if_needed(&mut self) {
+        let additions: usize = self
+            .additions
+            .iter_mut()
+            .map(|count| *count.get_mut())
+            .sum();
+        if additions < self.sample_size {
+            return;
+        }
+
+        for count in &mut self.sketch {
+            *count.get_mut() /= 2;
+        }
+
+        for bits in &mut self.doorkeeper {
+            *bits.get_mut() = 0;
+        }
+
+        for count in self.additions.iter_mut() {
+            *count.get_mut() = 0;
+        }
+    }
+
+    fn index(&self, row: usize, hash: u64) -> usize {
+        let mixed = hash.wrapping_mul(SEEDS[row]) >> 32;
+        row * (self.width_mask + 1) + (mixed as usize & self.width_mask)
+    }
+
+    /// Sets the doorkeeper bits of the hash and returns whether all of them were already set.
+    fn set_doorkeeper(&self, hash: u64) -> bool {
+        self.doorkeeper_bits(hash)
+            .fold(true, |was_set, (word, mask)| {
+                let previous = self.doorkeeper[word].fetch_or(mask, Ordering::Relaxed);
+                was_set && previous & mask != 0
+            })
+    }
+
+    fn doorkeeper_contains(&self, hash: u64) -> bool {
+        self.doorkeeper_bits(hash)
+            .all(|(word, mask)| self.doorkeeper[word].load(Ordering::Relaxed) & mask != 0)
+    }
+
+    /// Returns the word and the mask of every doorkeeper bit of the hash.
+    ///
+    /// The bits are taken from remixed hashes, since the bits of the hash itself are not
+    /// independent, e.g. with hashers of low entropy.
+    fn doorkeeper_bits(&self, hash: u64) -> impl Iterator<Item = (usize, u64)> {
+        let mask = self.doorkeeper_mask;
+        DOORKEEPER_SEEDS.into_iter().map(move |seed| {
+            let bit = (hash.wrapping_mul(seed) >> 32) as usize & mask;
+            (bit / 64, 1 << (bit % 64))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn it_counts_first_accesses_in_the_doorkeeper_only() {
+        // given
+        let tiny_lfu = TinyLfu::with_capacity(100);
+
+        // when
+        tiny_lfu.record(42);
+
+        // then
+        assert_eq!(tiny_lfu.estimate(42), 1);
+        assert!(
+            tiny_lfu
+                .sketch
+                .iter()
+                .all(|c| c.load(Ordering::Relaxed) == 0)
+        );
+    }
+
+    #[test]
+    fn it_estimates_frequencies() {
+        // given
+        let tiny_lfu = TinyLfu::with_capacity(100);
+
+        // when
+        for _ in 0..5 {
+            tiny_lfu.record(42);
+        }
+        tiny_lfu.record(7);
+
+        // then
+        assert_eq!(tiny_lfu.estimate(42), 5);
+        assert_eq!(tiny_lfu.estimate(7), 1);
+        assert_eq!(tiny_lfu.estimate(1_000), 0);
+    }
+
+    #[test]
+    fn it_spreads_the_doorkeeper_bits_of_similar_hashes() {
+        // given
+        let tiny_lfu = TinyLfu::with_capacity(16);
+
+        // when
+        let bits: HashSet<_> = (0..100u64)
+            .map(|i| (i << 38) | (i.wrapping_mul(0x9e37_79b9) & 0xffff_ffff))
+            .flat_map(|hash| tiny_lfu.doorkeeper_bits(hash).skip(1))
+            .collect();
+
+        // then
+        assert!(bits.len() > 16, "{}", bits.len());
+    }
+
+    #[test]
+    fn it_ages_frequencies() {
+        // given
+        let mut tiny_lfu = TinyLfu::with_capacity(1);
+        for _ in 0..9 {
+            tiny_lfu.record(42);
+        }
+
+        // when
+        tiny_lfu.age_if_needed();
+        let before_aging = tiny_lfu.estimate(42);
+        tiny_lfu.record(42);
+        tiny_lfu.age_if_needed();
+
+        // then
+        assert_eq!(before_aging, 9);
+        assert_eq!(tiny_lfu.estimate(42), 4);
+    }
+}
@@ -0,0 +1,374 @@
+use crate::cache::entry::Entry;
+use crate::cache::striped::{Striped, thread_stripe_count};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use parking_lot::Mutex;
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The number of reads a stripe buffers before they have to be applied to the eviction policy.
+const READ_BUFFER_CAPACITY: usize = 32;
+
+const EMPTY: u64 = 0;
+const TOMBSTONE: u64 = u64::MAX;
+
+/// An index of the entries of a shard that is read without locking the shard, see
+/// [`crate::Config::lock_free_reads`].
+///
+/// The shard publishes every entry it stores in the slot of the entry. Buckets map hashes to
+/// slots with open addressing and linear probing. A bucket holds the upper half of the hash and
+/// the slot plus one, so readers skip most other keys without loading their entries. Removed
+/// entries leave tombstones until the buckets are rebuilt, which happens when a quarter of the
+/// buckets are tombstones.
+///
+/// Only the shard changes the table, while it is locked for writing. Readers that run
+/// concurrently with a change may miss the changed entry, but they never return the value of
+/// another key.
+///
+/// Readers cannot update the eviction policy without locking the shard. Instead, every thread
+/// buffers the slots it reads in the stripe of its thread index. The shard applies the buffered
+/// reads before it evicts, and a reader that fills its buffer applies them if it can lock the
+/// shard for reading without waiting. Reads are dropped while the buffer of their stripe is full
+/// or applied by another thread, so under heavy contention the eviction policy only sees a sample
+/// of the reads.
+pub(crate) struct ReadTable<K, V> {
+    table: ArcSwap<Table<K, V>>,
+    stripes: Striped<ReadStripe>,
+}
+
+struct Table<K, V> {
+    buckets: Box<[AtomicU64]>,
+    // the number of buckets that hold a slot or a tombstone
+    used_buckets: AtomicUsize,
+    // shared with the tables that replace this one while the capacity stays the same
+    slots: Arc<[ArcSwapOption<Entry<K, V>>]>,
+}
+
+#[derive(Default)]
+struct ReadStripe {
+    hit_count: AtomicU64,
+    miss_count: AtomicU64,
+    reads: Mutex<Vec<(usize, u64)>>,
+}
+
+/// The result of a read from a [`ReadTable`].
+pub(crate) struct Read<V> {
+    pub(crate) value: Option<V>,
+    /// Whether the read buffer of the reading thread is full and should be applied.
+    pub(crate) buffer_full: bool,
+}
+
+impl<K, V> ReadTable<K, V> {
+    /// Creates an empty table for a shard with the specified capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity does not fit into the lower half of a bucket.
+    pub(crate) fn with_capacity(capacity: usize) -> ReadTable<K, V> {
+        ReadTable {
+            table: ArcSwap::from_pointee(Table::with_slots(empty_slots(capacity))),
+            stripes: Striped::new(thread_stripe_count()),
+        }
+    }
+
+    pub(crate) fn get<Q>(&self, hash: u64, key: &Q) -> Read<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+        V: Clone,
+    {
+        let stripe = self.stripes.get();
+        let Some((slot, value)) = self.table.load().find(hash, key) else {
+            stripe.miss_count.fetch_add(1, Ordering::Relaxed);
+            return Read {
+                value: None,
+                buffer_full: false,
+            };
+        };
+
+        stripe.hit_count.fetch_add(1, Ordering::Relaxed);
+
+        let buffer_full = match stripe.reads.try_lock() {
+            Some(mut reads) => {
+                if reads.len() < READ_BUFFER_CAPACITY {
+                    reads.push((slot, hash));
+                }
+                reads.len() >= READ_BUFFER_CAPACITY
+            }
+            None => false,
+        };
+
+        Read {
+            value: Some(value),
+            buffer_full,
+        }
+    }
+
+    /// Hands the slot and hash of every buffered read to `apply` and empties the buffers.
+    pub(crate) fn drain_reads(&self, mut apply: impl FnMut(usize, u64)) {
+        for stripe in self.stripes.iter() {
+            for (slot, hash) in stripe.reads.lock().drain(..) {
+                apply(slot, hash);
+            }
+        }
+    }
+
+    /// Publishes a new entry in an empty slot.
+    pub(crate) fn insert(&self, slot: usize, entry: &Arc<Entry<K, V>>) {
+        let table = self.table.load();
+        table.slots[slot].store(Some(entry.clone()));
+        table.insert_bucket(entry.hash, slot);
+
+        if table.used_buckets.load(Ordering::Relaxed) > table.buckets.len() / 4 * 3 {
+            let rebuilt = Table::with_slots(table.slots.clone());
+            self.table.store(Arc::new(rebuilt));
+        }
+    }
+
+    /// Publishes an entry that replaces the entry of the same key in `slot`.
+    pub(crate) fn replace(&self, slot: usize, entry: &Arc<Entry<K, V>>) {
+        self.table.load().slots[slot].store(Some(entry.clone()));
+    }
+
+    /// Removes the entry with `hash` from `slot`.
+    pub(crate) fn remove(&self, slot: usize, hash: u64) {
+        let table = self.table.load();
+        table.remove_bucket(hash, slot);
+        table.slots[slot].store(None);
+    }
+
+    /// Replaces the table with one that holds `entries`, e.g. after the capacity of the shard
+    /// changed.
+    pub(crate) fn rebuild(&self, entries: &[Option<Arc<Entry<K, V>>>]) {
+        let slots: Arc<[ArcSwapOption<Entry<K, V>>]> = entries
+            .iter()
+            .map(|entry| ArcSwapOption::new(entry.clone()))
+            .collect();
+        self.table.store(Arc::new(Table::with_slots(slots)));
+    }
+
+    pub(crate) fn hit_count(&self) -> u64 {
+        self.stripes
+            .iter()
+            .map(|stripe| stripe.hit_count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub(crate) fn miss_count(&self) -> u64 {
+        self.stripes
+            .iter()
+            .map(|stripe| stripe.miss_count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub(crate) fn reset_counters(&self) {
+        for stripe in self.stripes.iter() {
+            stripe.hit_count.store(0, Ordering::Relaxed);
+            stripe.miss_count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<K, V> Debug for ReadTable<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let table = self.table.load();
+        f.debug_struct("ReadTable")
+            .field("capacity", &table.slots.len())
+            .field("buckets", &table.buckets.len())
+            .finish()
+    }
+}
+
+fn empty_slots<K, V>(capacity: usize) -> Arc<[ArcSwapOption<Entry<K, V>>]> {
+    (0..capacity).map(|_| ArcSwapOption::empty()).collect()
+}
+
+impl<K, V> Table<K, V> {
+    /// Creates a table for `slots` and adds a bucket for every entry in them.
+    fn with_slots(slots: Arc<[ArcSwapOption<Entry<K, V>>]>) -> Table<K, V> {
+        assert!(
+            slots.len() < u32::MAX as usize,
+            "shards with lock-free reads hold less than u32::MAX entries"
+        );
+
+        let bucket_count = (slots.len() * 2).next_power_of_two().max(2);
+        let table = Table {
+            buckets: (0..bucket_count).map(|_| AtomicU64::new(EMPTY)).collect(),
+            used_buckets: AtomicUsize::new(0),
+            slots,
+        };
+
+        for (slot, entry) in table.slots.iter().enumerate() {
+            if let Some(entry) = &*entry.load() {
+                table.insert_bucket(entry.hash, slot);
+            }
+        }
+
+        table
+    }
+
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<(usize, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+        V: Clone,
+    {
+        for idx in self.probe(hash) {
+            let bucket = self.buckets[idx].load(Ordering::Acquire);
+            if bucket == EMPTY {
+                return None;
+            }
+            if bucket == TOMBSTONE || bucket >> 32 != hash >> 32 {
+                continue;
+            }
+
+            let slot = (bucket & u64::from(u32::MAX)) as usize - 1;
+            if let Some(entry) = &*self.slots[slot].load()
+                && entry.hash == hash
+                && entry.key.borrow() == key
+            {
+                return Some((slot, entry.value.clone()));
+            }
+        }
+
+        None
+    }
+
+    fn insert_bucket(&self, hash: u64, slot: usize) {
+        for idx in self.probe(hash) {
+            let bucket = self.buckets[idx].load(Ordering::Relaxed);
+            if bucket == EMPTY || bucket == TOMBSTONE {
+                if bucket == EMPTY {
+                    self.used_buckets.fetch_add(1, Ordering::Relaxed);
+                }
+                self.buckets[idx].store(bucket_of(hash, slot), Ordering::Release);
+                return;
+            }
+        }
+
+        unreachable!("a read table is rebuilt before its buckets run out");
+    }
+
+    fn remove_bucket(&self, hash: u64, slot: usize) {
+        let removed = bucket_of(hash, slot);
+        for idx in self.probe(hash) {
+            let bucket = self.buckets[idx].load(Ordering::Relaxed);
+            if bucket == EMPTY {
+                return;
+            }
+            if bucket == removed {
+                self.buckets[idx].store(TOMBSTONE, Ordering::Release);
+                return;
+            }
+        }
+    }
+
+    /// Returns the indices of the buckets to look at for `hash`, starting at its home bucket.
+    fn probe(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let mask = self.buckets.len() - 1;
+        let home = (hash ^ (hash >> 32)) as usize & mask;
+        (0..self.buckets.len()).map(move |offset| (home + offset) & mask)
+    }
+}
+
+fn bucket_of(hash: u64, slot: usize) -> u64 {
+    (hash >> 32 << 32) | (slot as u64 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(table: &ReadTable<u64, u64>, slot: usize, key: u64) -> Arc<Entry<u64, u64>> {
+        let entry = Arc::new(Entry::new(key, key * 10, key));
+        table.insert(slot, &entry);
+        entry
+    }
+
+    #[test]
+    fn it_finds_published_entries() {
+        // given
+        let table = ReadTable::with_capacity(4);
+
+        // when
+        insert(&table, 2, 1);
+        table.replace(2, &Arc::new(Entry::new(1, 11, 1)));
+
+        // then
+        assert_eq!(table.get(1, &1).value, Some(11));
+        assert_eq!(table.get(2, &2).value, None);
+        assert_eq!((table.hit_count(), table.miss_count()), (1, 1));
+    }
+
+    #[test]
+    fn it_tells_keys_with_the_same_hash_apart() {
+        // given
+        let table = ReadTable::with_capacity(4);
+
+        // when
+        table.insert(0, &Arc::new(Entry::new(1, 10, 42)));
+        table.insert(1, &Arc::new(Entry::new(2, 20, 42)));
+
+        // then
+        assert_eq!(table.get(42, &1).value, Some(10));
+        assert_eq!(table.get(42, &2).value, Some(20));
+        assert_eq!(table.get(42, &3).value, None);
+    }
+
+    #[test]
+    fn it_forgets_removed_entries_and_reuses_their_buckets() {
+        // given
+        let table = ReadTable::with_capacity(4);
+
+        // when
+        for key in 0..1_000 {
+            insert(&table, (key % 4) as usize, key);
+            if key >= 3 {
+                table.remove(((key - 3) % 4) as usize, key - 3);
+            }
+        }
+
+        // then
+        assert_eq!(table.get(996, &996).value, None);
+        for key in 997..1_000 {
+            assert_eq!(table.get(key, &key).value, Some(key * 10));
+        }
+        assert!(table.table.load().buckets.len() <= 8);
+    }
+
+    #[test]
+    fn it_moves_entries_into_rebuilt_tables() {
+        // given
+        let table = ReadTable::with_capacity(4);
+        insert(&table, 3, 1);
+
+        // when
+        let entries = vec![Some(Arc::new(Entry::new(1, 10, 1))), None];
+        table.rebuild(&entries);
+
+        // then
+        assert_eq!(table.get(1, &1).value, Some(10));
+        assert_eq!(table.table.load().slots.len(), 2);
+    }
+
+    #[test]
+    fn it_buffers_reads_until_they_are_applied() {
+        // given
+        let table = ReadTable::with_capacity(4);
+        insert(&table, 2, 1);
+
+        // when
+        let full: Vec<_> = (0..READ_BUFFER_CAPACITY)
+            .map(|_| table.get(1, &1).buffer_full)
+            .collect();
+        let mut applied = Vec::new();
+        table.drain_reads(|slot, hash| applied.push((slot, hash)));
+
+        // then
+        assert!(full[..READ_BUFFER_CAPACITY - 1].iter().all(|full| !full));
+        assert!(full[READ_BUFFER_CAPACITY - 1]);
+        assert_eq!(applied, vec![(2, 1); READ_BUFFER_CAPACITY]);
+        assert!(!table.get(1, &1).buffer_full);
+    }
+}
@@ -24,10 +24,6 @@ impl<T> RingBuffer<T> {
         self.len == self.buffer.capacity()
     }
 
-    pub(crate) fn get(&self, index: usize) -> Option<&T> {
-        self.buffer.get(index).and_then(Option::as_ref)
-    }
-
     /// Adds an item to the back of the queue.
     pub(crate) fn push_back(&mut self, value: T) -> Option<usize> {
         if self.is_full() {
@@ -79,13 +75,11 @@ impl<T> RingBuffer<T> {
         }
     }
 
-    /// Removes an element from the queue. Note that this will not immediately increase the len of
-    /// the queue. Only calling using [RingBuffer::pop_front] will do this.
-    ///
-    /// ## Panics
-    /// This method doesn't do an index check. Out of bound accesses will panic.
-    pub(crate) fn remove(&mut self, index: usize) -> Option<T> {
-        self.buffer[index].take()
+    /// Returns an iterator over the elements of the queue, from front to back.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len)
+            .map(|offset| self.wrap_add(self.head, offset))
+            .filter_map(|index| self.buffer[index].as_ref())
     }
 
     fn wrap_add(&self, idx: usize, addend: usize) -> usize {
@@ -249,42 +243,19 @@ mod tests {
     }
 
     #[test]
-    fn it_handles_deletions() {
+    fn it_iterates_from_front_to_back() {
         // given
-        let mut ring_buffer = RingBuffer::with_capacity(5);
+        let mut ring_buffer = RingBuffer::with_capacity(3);
         ring_buffer.push_back(String::from("first")).unwrap();
         ring_buffer.push_back(String::from("second")).unwrap();
         ring_buffer.push_back(String::from("third")).unwrap();
-        ring_buffer.push_back(String::from("fourth")).unwrap();
-        ring_buffer.push_back(String::from("fifth")).unwrap();
-        ring_buffer.pop_front();
         ring_buffer.pop_front();
-        ring_buffer.pop_front();
-
-        ring_buffer.remove(3);
-        // buffer.cap   - - - - -
-        // buffer.len   - - - - -
-        // len                - -
-        // head               |
-        //             [N N N N S ]
-        assert_eq!(ring_buffer.head, 3);
-        assert_eq!(ring_buffer.buffer.len(), 5);
-        assert_eq!(ring_buffer.len, 2);
+        ring_buffer.push_back(String::from("fourth")).unwrap();
 
         // when
-        let item = ring_buffer.pop_front().unwrap();
+        let items: Vec<&String> = ring_buffer.iter().collect();
 
         // then
-
-        // buffer.cap   - - - - -
-        // buffer.len   - - - - -
-        // len
-        // head         |
-        //             [N N N N N ]
-        assert!(ring_buffer.is_empty());
-        assert_eq!(ring_buffer.head, 0);
-        assert_eq!(ring_buffer.buffer.len(), 5);
-        assert_eq!(ring_buffer.len, 0);
-        assert_eq!(item, "fifth")
+        assert_eq!(items, vec!["second", "third", "fourth"]);
     }
 }
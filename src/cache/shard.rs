@@ -1,228 +1,655 @@
-use crate::cache::RandomState;
-use crate::cache::entry::{Entry, EntryPointer};
-use crate::cache::fixed_size_hash_table::FixedSizeHashTable;
-use crate::cache::ring_buffer::RingBuffer;
+use crate::cache::entry::Entry;
+use crate::cache::pin;
+#[cfg(feature = "persistence")]
+use crate::cache::policy::prioritized::Ghost;
+use crate::cache::policy::prioritized::{PolicyFactory, Prioritized};
+use crate::cache::policy::{EvictionPolicy, Insertion};
+use crate::cache::read_table::ReadTable;
+use crate::cache::slots::Slots;
 use crate::cache::stats::Counters;
+use crate::{PinError, Placement, Priority, ShardStats};
+use hashbrown::HashTable;
 use std::borrow::Borrow;
-use std::cmp;
-use std::collections::HashMap;
-use std::hash::{BuildHasher, Hash};
-
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A shard of the cache.
+///
+/// Entries are stored in a fixed number of slots. `entry_pointers` maps the hashes of the keys to
+/// slots and the eviction policy decides which slot is freed when the shard is full. Keys are
+/// only stored in their entries: `entry_pointers` holds slot indices and the ghost queues of the
+/// eviction policies hold hashes, so keys are moved in once and never cloned.
+///
+/// Pinned entries are removed from the eviction policy, so they are never evicted. At most
+/// `max_pinned` entries can be pinned at the same time.
+///
+/// Replacing the value of an entry hands it to the eviction policy as a new entry, unless the
+/// shard updates entries in place. Then the entry keeps its slot and its state in the policy.
+///
+/// When the capacity changes, surviving entries are moved into the lowest slots and the eviction
+/// policies are rebuilt for the new number of slots.
+///
+/// A shard with lock-free reads publishes its entries to a [`ReadTable`], which readers use
+/// without locking the shard. Their reads are applied to the eviction policy before it evicts.
+///
+/// A shard can share a counter of unused slots with the other shards of the cache, so a full
+/// shard can tell whether it may borrow capacity from another shard. Borrowed capacity is taken
+/// from free slots at the end of the lending shard and added to the end of the borrowing shard, so
+/// no entries move and the eviction policies only adapt to the new number of slots.
+///
+/// All methods take the hash of the key as computed by the cache's `hash_builder`. The hash is
+/// stored with the entry, reused for the lookup in `entry_pointers` and handed to the eviction
+/// policy, so keys are hashed only once.
 #[derive(Debug)]
-pub(crate) struct Shard<K, V, S = RandomState> {
-    entry_pointers: HashMap<K, EntryPointer, S>,
-    small_queue: RingBuffer<Entry<K, V>>,
-    main_queue: RingBuffer<Entry<K, V>>,
-    ghost_queue: FixedSizeHashTable<K, S>,
+pub(crate) struct Shard<K, V> {
+    entry_pointers: HashTable<usize>,
+    entries: Slots<K, V>,
+    free_slots: Vec<usize>,
+    policy: Prioritized,
+    pinned: Vec<bool>,
+    pinned_len: usize,
+    max_pinned: usize,
+    max_pinned_percent: u8,
+    update_in_place: bool,
+    unused_capacity: Option<Arc<AtomicUsize>>,
     counters: Counters,
 }
 
-impl<K, V, S> Shard<K, V, S>
-where
-    S: BuildHasher + Clone,
-{
-    pub(crate) fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
-        let small_fifo_queue_size = cmp::max(capacity / 10, 1);
-        let main_fifo_queue_size = cmp::max(capacity - small_fifo_queue_size, 1);
+/// The eviction state of an entry that is saved in a snapshot.
+#[cfg(feature = "persistence")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct EntryState {
+    pub(crate) placement: Placement,
+    pub(crate) priority: Priority,
+    pub(crate) pinned: bool,
+}
 
+impl<K, V> Shard<K, V> {
+    pub(crate) fn with_capacity_and_policy(
+        capacity: usize,
+        make_policy: PolicyFactory,
+        max_pinned_percent: u8,
+    ) -> Self {
         Self {
-            entry_pointers: HashMap::<K, EntryPointer, S>::with_capacity_and_hasher(
-                capacity,
-                hash_builder.clone(),
-            ),
-            small_queue: RingBuffer::with_capacity(small_fifo_queue_size),
-            main_queue: RingBuffer::with_capacity(main_fifo_queue_size),
-            ghost_queue: FixedSizeHashTable::with_capacity_and_hasher(
-                main_fifo_queue_size,
-                hash_builder,
-            ),
+            entry_pointers: HashTable::with_capacity(capacity),
+            entries: Slots::with_capacity(capacity),
+            free_slots: (0..capacity).rev().collect(),
+            policy: Prioritized::new(capacity, make_policy),
+            pinned: vec![false; capacity],
+            pinned_len: 0,
+            max_pinned: pin::max_pinned(capacity, max_pinned_percent),
+            max_pinned_percent,
+            update_in_place: false,
+            unused_capacity: None,
             counters: Counters::default(),
         }
     }
+
+    /// Keeps the queue and the access frequency of entries whose values are replaced, see
+    /// [`crate::Config::update_in_place`].
+    pub(crate) fn update_in_place(self) -> Self {
+        Self {
+            update_in_place: true,
+            ..self
+        }
+    }
+
+    /// Publishes the entries of the empty shard to `reads`, see
+    /// [`crate::Config::lock_free_reads`].
+    pub(crate) fn lock_free_reads(self, reads: Arc<ReadTable<K, V>>) -> Self {
+        Self {
+            entries: Slots::shared(self.capacity(), reads),
+            ..self
+        }
+    }
+
+    /// Adds the unused slots of the shard to `unused_capacity` and keeps it up to date.
+    pub(crate) fn track_unused_capacity(&mut self, unused_capacity: Arc<AtomicUsize>) {
+        unused_capacity.fetch_add(self.free_slots.len(), Ordering::AcqRel);
+        self.unused_capacity = Some(unused_capacity);
+    }
+
+    /// Returns the number of slots that do not hold an entry.
+    pub(crate) fn unused_capacity(&self) -> usize {
+        self.free_slots.len()
+    }
 }
 
-impl<K, V, S> Shard<K, V, S>
+impl<K, V> Shard<K, V>
 where
-    K: Clone + Eq + Hash,
-    S: BuildHasher,
+    K: Eq,
     V: Clone,
 {
-    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
-        let previous_item = if self.entry_pointers.contains_key(&key) {
-            match self.entry_pointers.get(&key).expect("just checked") {
-                EntryPointer::MainQueue(index) => {
-                    self.main_queue.remove(*index).map(|entry| entry.value)
-                }
-                EntryPointer::SmallQueue(index) => {
-                    self.small_queue.remove(*index).map(|entry| entry.value)
-                }
-            }
-        } else {
-            None
-        };
+    pub(crate) fn insert(&mut self, hash: u64, key: K, value: V) -> Option<V> {
+        self.insert_with_insertion(key, value, Insertion::new(hash, None))
+    }
 
-        let entry = Entry::new(key.clone(), value);
+    pub(crate) fn insert_with_cost(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: V,
+        cost: u64,
+        size: u64,
+    ) -> Option<V> {
+        let insertion = Insertion::new(hash, None).with_cost(cost, size);
+        self.insert_with_insertion(key, value, insertion)
+    }
 
-        if self.ghost_queue.contains(&key) {
-            self.insert_into_main_queue(entry);
-        } else {
-            self.insert_into_small_queue(entry);
+    pub(crate) fn insert_with_priority(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: V,
+        priority: Priority,
+    ) -> Option<V> {
+        let insertion = Insertion::new(hash, None).with_priority(priority);
+        self.insert_with_insertion(key, value, insertion)
+    }
+
+    /// Places an entry into the queue given by `placement`, bypassing the ghost queue lookup.
+    pub(crate) fn insert_with_placement(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: V,
+        placement: Placement,
+    ) {
+        self.insert_with_insertion(key, value, Insertion::new(hash, Some(placement)));
+    }
+
+    /// Inserts an entry with the state it was saved with. A pinned entry is pinned again, unless
+    /// the shard already holds `max_pinned` pinned entries. Then it is inserted unpinned.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn restore(&mut self, hash: u64, key: K, value: V, state: EntryState) {
+        if state.pinned && self.pinned_len < self.max_pinned {
+            // pinning cannot fail below the limit
+            if let Ok((Some(slot), _)) = self.insert_pinned_into_slot(hash, key, value) {
+                self.policy.set_priority(slot, state.priority);
+            }
+            return;
         }
 
-        previous_item
+        let insertion = Insertion::new(hash, Some(state.placement)).with_priority(state.priority);
+        self.insert_with_insertion(key, value, insertion);
     }
 
-    fn insert_into_main_queue(&mut self, entry: Entry<K, V>) -> Option<V> {
-        if self.main_queue.is_full() {
-            self.evict_main_queue();
+    #[cfg(feature = "persistence")]
+    pub(crate) fn restore_ghost(&mut self, ghost: Ghost) {
+        self.policy.restore(ghost);
+    }
+
+    fn insert_with_insertion(&mut self, key: K, value: V, insertion: Insertion) -> Option<V> {
+        let hash = insertion.hash;
+
+        if let Some(slot) = self.find_slot(hash, &key) {
+            // the key is already present: replace the value and treat it as a new insertion,
+            // unless it is updated in place
+            let previous_value = self.entries.replace(slot, Entry::new(key, value, hash));
+
+            if self.pinned[slot] {
+                self.policy.set_priority(slot, insertion.priority);
+                return previous_value;
+            }
+
+            let in_place = self.update_in_place
+                && insertion.placement.is_none()
+                && self.policy.priority_of(slot) == insertion.priority;
+            if in_place {
+                return previous_value;
+            }
+
+            self.policy.on_remove(slot);
+            self.policy.on_insert(slot, insertion);
+
+            return previous_value;
         }
 
-        let key = entry.key.clone();
+        let slot = self.allocate_slot()?;
 
-        let index = self
-            .main_queue
-            .push_back(entry)
-            .expect("expecting space after eviction");
+        self.entries.insert(slot, Entry::new(key, value, hash));
 
+        let entries = &self.entries;
         self.entry_pointers
-            .insert(key, EntryPointer::MainQueue(index));
+            .insert_unique(hash, slot, |slot| Self::hash_of(entries, *slot));
+
+        self.policy.on_insert(slot, insertion);
 
         None
     }
 
-    fn evict_main_queue(&mut self) {
-        loop {
-            if let Some(entry) = self.main_queue.pop_front() {
-                let num_accessed = entry.get_num_accessed();
-                if num_accessed > 0 {
-                    let decremented_by_one = cmp::max(0, num_accessed - 1);
-                    self.reinsert_into_main_queue(entry, decremented_by_one);
-                    continue;
-                } else {
-                    self.entry_pointers.remove(&entry.key);
-                    self.counters.increment_eviction_count();
-                    return;
-                }
-            }
+    /// Inserts an entry and pins it. Fails if the entry is not pinned yet and the shard already
+    /// holds `max_pinned` pinned entries.
+    pub(crate) fn insert_pinned(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>, PinError> {
+        self.insert_pinned_into_slot(hash, key, value)
+            .map(|(_, previous_value)| previous_value)
+    }
 
-            return;
+    /// Like [`Shard::insert_pinned`], but also returns the slot of the entry, if it was stored.
+    /// Existing entries keep their class, new entries are pinned in the default class.
+    fn insert_pinned_into_slot(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: V,
+    ) -> Result<(Option<usize>, Option<V>), PinError> {
+        if let Some(slot) = self.find_slot(hash, &key) {
+            self.pin_slot(slot)?;
+            let previous_value = self.entries.replace(slot, Entry::new(key, value, hash));
+            return Ok((Some(slot), previous_value));
         }
-    }
 
-    fn insert_into_small_queue(&mut self, entry: Entry<K, V>) -> Option<V> {
-        if self.small_queue.is_full() {
-            self.evict_small_queue();
+        if self.pinned_len >= self.max_pinned {
+            return Err(PinError::LimitReached);
         }
 
-        let key = entry.key.clone();
+        let Some(slot) = self.allocate_slot() else {
+            return Ok((None, None));
+        };
 
-        let index = self
-            .small_queue
-            .push_back(entry)
-            .expect("there must be space after eviction");
+        self.entries.insert(slot, Entry::new(key, value, hash));
 
+        let entries = &self.entries;
         self.entry_pointers
-            .insert(key, EntryPointer::SmallQueue(index));
+            .insert_unique(hash, slot, |slot| Self::hash_of(entries, *slot));
 
-        None
+        self.pinned[slot] = true;
+        self.pinned_len += 1;
+        self.policy.set_priority(slot, Priority::default());
+
+        Ok((Some(slot), None))
     }
 
-    fn evict_small_queue(&mut self) {
-        if let Some(entry) = self.small_queue.pop_front() {
-            if entry.get_num_accessed() > 1 {
-                // add the entry to the main queue, reset the access counter, and update the pointer
+    pub(crate) fn pin<Q>(&mut self, hash: u64, key: &Q) -> Result<(), PinError>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        let slot = self.find_slot(hash, key).ok_or(PinError::NotFound)?;
+        self.pin_slot(slot)
+    }
 
-                if self.main_queue.is_full() {
-                    self.evict_main_queue();
-                }
+    /// Unpins an entry and hands it back to the eviction policy. Returns whether the entry was
+    /// pinned.
+    pub(crate) fn unpin<Q>(&mut self, hash: u64, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        let Some(slot) = self.find_slot(hash, key) else {
+            return false;
+        };
 
-                let pointer = self.entry_pointers.get_mut(&entry.key).expect(
-                    "an entry popped from the small queue must be present in the entry pointers",
-                );
+        if !self.pinned[slot] {
+            return false;
+        }
 
-                entry.set_num_accessed(0);
+        self.pinned[slot] = false;
+        self.pinned_len -= 1;
+        let insertion = Insertion::new(hash, None).with_priority(self.policy.priority_of(slot));
+        self.policy.on_insert(slot, insertion);
 
-                let index = self
-                    .main_queue
-                    .push_back(entry)
-                    .expect("there must be space after eviction");
+        true
+    }
 
-                *pointer = EntryPointer::MainQueue(index);
-            } else {
-                // remove the entry and add the key to the ghost queue
+    fn pin_slot(&mut self, slot: usize) -> Result<(), PinError> {
+        if self.pinned[slot] {
+            return Ok(());
+        }
 
-                self.entry_pointers.remove(&entry.key);
-                self.counters.increment_eviction_count();
-                self.ghost_queue.insert(entry.key);
+        if self.pinned_len >= self.max_pinned {
+            return Err(PinError::LimitReached);
+        }
+
+        self.policy.on_remove(slot);
+        self.pinned[slot] = true;
+        self.pinned_len += 1;
+
+        Ok(())
+    }
+
+    /// Changes the number of slots of the shard.
+    ///
+    /// When shrinking, entries are evicted in the order of the eviction policy until the
+    /// remaining entries fit. Pinned entries are never evicted, so the shard keeps room for all
+    /// of them and one evictable entry.
+    ///
+    /// Slots are added to or removed from the end of the shard, so the eviction policy keeps its
+    /// state, see [`EvictionPolicy::set_capacity`]. Only if entries remain beyond the new
+    /// capacity, they are moved into free slots and the policy is rebuilt.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        let capacity = if self.pinned_len > 0 {
+            capacity.max(self.pinned_len + 1)
+        } else {
+            capacity
+        };
+
+        self.apply_reads();
+
+        while self.len() > capacity {
+            if self.evict().is_none() {
+                break;
+            }
+        }
+
+        if self
+            .entries
+            .iter()
+            .skip(capacity)
+            .all(|entry| entry.is_none())
+        {
+            self.resize_at_end(capacity);
+            return;
+        }
+
+        let old_capacity = self.capacity();
+
+        // entries in slots beyond the new capacity are moved into the lowest free slots
+        let mut new_slots: Vec<Option<usize>> = (0..old_capacity)
+            .map(|slot| (slot < capacity && self.entries.get(slot).is_some()).then_some(slot))
+            .collect();
+        let mut free_slots: Vec<usize> = (0..capacity)
+            .rev()
+            .filter(|slot| self.entries.get(*slot).is_none())
+            .collect();
+
+        for (old_slot, new_slot) in new_slots.iter_mut().enumerate().skip(capacity) {
+            if self.entries.get(old_slot).is_none() {
+                continue;
+            }
+            match free_slots.pop() {
+                Some(slot) => *new_slot = Some(slot),
+                // the policy declined to evict more entries
+                None => self.counters.increment_eviction_count(),
+            }
+        }
+
+        let mut pinned = vec![false; capacity];
+        let mut relocations = vec![None; old_capacity];
+        let mut pinned_classes = Vec::new();
+        for (old_slot, new_slot) in new_slots.iter().enumerate() {
+            let (Some(new_slot), Some(entry)) = (*new_slot, self.entries.get(old_slot)) else {
+                continue;
             };
+
+            pinned[new_slot] = self.pinned[old_slot];
+            if pinned[new_slot] {
+                pinned_classes.push((new_slot, self.policy.priority_of(old_slot)));
+            } else {
+                relocations[old_slot] = Some((new_slot, entry.hash));
+            }
+        }
+
+        self.entries.relocate(capacity, &new_slots);
+
+        let mut entry_pointers = HashTable::with_capacity(capacity);
+        for (slot, entry) in self.entries.iter().enumerate() {
+            if let Some(entry) = entry {
+                let entries = &self.entries;
+                entry_pointers
+                    .insert_unique(entry.hash, slot, |slot| Self::hash_of(entries, *slot));
+            }
+        }
+
+        self.entry_pointers = entry_pointers;
+        if let Some(unused_capacity) = &self.unused_capacity {
+            unused_capacity.fetch_sub(self.free_slots.len(), Ordering::AcqRel);
+            unused_capacity.fetch_add(free_slots.len(), Ordering::AcqRel);
+        }
+        self.free_slots = free_slots;
+        self.pinned_len = pinned.iter().filter(|pinned| **pinned).count();
+        self.pinned = pinned;
+        self.max_pinned = pin::max_pinned(capacity, self.max_pinned_percent);
+        self.policy.resize(capacity, &relocations);
+        for (slot, priority) in pinned_classes {
+            self.policy.set_priority(slot, priority);
+        }
+    }
+
+    /// Gives up to half of the unused slots at the end of the shard to another shard. Returns the
+    /// number of slots given up.
+    ///
+    /// Like [`Shard::set_capacity`], the shard keeps room for all of its pinned entries and one
+    /// evictable entry.
+    pub(crate) fn lend_capacity(&mut self) -> usize {
+        let capacity = self.capacity();
+        let min_capacity = if self.pinned_len > 0 {
+            self.pinned_len + 1
+        } else {
+            0
+        };
+
+        let lending = (0..capacity)
+            .rev()
+            .take(self.unused_capacity().div_ceil(2))
+            .take_while(|slot| self.entries.get(*slot).is_none())
+            .count()
+            .min(capacity.saturating_sub(min_capacity));
+
+        if lending > 0 {
+            self.resize_at_end(capacity - lending);
+        }
+
+        lending
+    }
+
+    /// Adds slots lent by another shard to the end of the shard.
+    pub(crate) fn borrow_capacity(&mut self, borrowed: usize) {
+        self.resize_at_end(self.capacity() + borrowed);
+    }
+
+    /// Adds slots to or removes free slots from the end of the shard, so entries keep their slots
+    /// and the eviction policy keeps its state, unless it does not support
+    /// [`EvictionPolicy::set_capacity`].
+    fn resize_at_end(&mut self, capacity: usize) {
+        self.apply_reads();
+
+        let old_capacity = self.capacity();
+        let old_unused_capacity = self.unused_capacity();
+
+        self.entries.resize(capacity);
+        self.pinned.resize(capacity, false);
+
+        if capacity < old_capacity {
+            self.free_slots.retain(|slot| *slot < capacity);
+        } else {
+            // new slots are used after the existing free slots, which are popped from the back
+            let mut free_slots: Vec<usize> = (old_capacity..capacity).rev().collect();
+            free_slots.extend_from_slice(&self.free_slots);
+            self.free_slots = free_slots;
+        }
+
+        if let Some(unused_capacity) = &self.unused_capacity {
+            unused_capacity.fetch_sub(old_unused_capacity, Ordering::AcqRel);
+            unused_capacity.fetch_add(self.free_slots.len(), Ordering::AcqRel);
+        }
+        self.max_pinned = pin::max_pinned(capacity, self.max_pinned_percent);
+
+        if !self.policy.set_capacity(capacity) {
+            let relocations: Vec<_> = self
+                .entries
+                .iter()
+                .zip(&self.pinned)
+                .enumerate()
+                .map(|(slot, (entry, pinned))| {
+                    entry.filter(|_| !pinned).map(|entry| (slot, entry.hash))
+                })
+                .collect();
+            let pinned_classes: Vec<_> = (0..capacity)
+                .filter(|slot| self.pinned[*slot])
+                .map(|slot| (slot, self.policy.priority_of(slot)))
+                .collect();
+
+            self.policy.resize(capacity, &relocations);
+            for (slot, priority) in pinned_classes {
+                self.policy.set_priority(slot, priority);
+            }
+        }
+    }
+
+    /// Returns a free slot, evicting an entry if the shard is full.
+    fn allocate_slot(&mut self) -> Option<usize> {
+        if let Some(slot) = self.free_slots.pop() {
+            if let Some(unused_capacity) = &self.unused_capacity {
+                unused_capacity.fetch_sub(1, Ordering::AcqRel);
+            }
+            return Some(slot);
         }
+
+        self.evict()
     }
 
-    fn reinsert_into_main_queue(&mut self, entry: Entry<K, V>, num_accessed: u8) {
-        let pointer = self
-            .entry_pointers
-            .get_mut(&entry.key)
-            .expect("an entry popped from the main queue must be present in the entry pointers");
+    /// Evicts an entry as decided by the eviction policy and returns its now empty slot.
+    fn evict(&mut self) -> Option<usize> {
+        self.apply_reads();
+
+        let slot = self.policy.evict()?;
 
-        entry.set_num_accessed(num_accessed);
+        let hash = Self::hash_of(&self.entries, slot);
+        self.entries.remove(slot);
+
+        self.entry_pointers
+            .find_entry(hash, |s| *s == slot)
+            .expect("an evicted entry must be present in the entry pointers")
+            .remove();
 
-        let index = self
-            .main_queue
-            .push_back(entry)
-            .expect("there must be space after eviction");
+        self.counters.increment_eviction_count();
 
-        *pointer = EntryPointer::MainQueue(index);
+        Some(slot)
     }
 
-    pub(crate) fn get<Q>(&self, key: &Q) -> Option<V>
+    pub(crate) fn get<Q>(&self, hash: u64, key: &Q) -> Option<V>
     where
         K: Borrow<Q>,
-        Q: ?Sized + Hash + Eq,
+        Q: ?Sized + Eq,
     {
-        let Some(entry_pointer) = self.entry_pointers.get(key) else {
+        let Some(slot) = self.find_slot(hash, key) else {
             self.counters.increment_miss_count();
             return None;
         };
 
         self.counters.increment_hit_count();
-        match entry_pointer {
-            EntryPointer::MainQueue(index) => {
-                let entry = self
-                    .main_queue
-                    .get(*index)
-                    .expect("an entry must exist for an entry pointer");
-                Self::update_access_count(entry);
-                Some(entry.value.clone())
+
+        let entry = self
+            .entries
+            .get(slot)
+            .expect("an entry must exist for an entry pointer");
+
+        if !self.pinned[slot] {
+            self.policy.on_access(slot);
+        }
+
+        Some(entry.value.clone())
+    }
+
+    fn find_slot<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        let entries = &self.entries;
+        self.entry_pointers
+            .find(hash, |slot| {
+                entries
+                    .get(*slot)
+                    .is_some_and(|entry| entry.key.borrow() == key)
+            })
+            .copied()
+    }
+
+    fn hash_of(entries: &Slots<K, V>, slot: usize) -> u64 {
+        entries
+            .get(slot)
+            .expect("an entry must exist for an entry pointer")
+            .hash
+    }
+}
+
+impl<K, V> Shard<K, V> {
+    /// Returns the entries of the shard together with their eviction state, in the order in which
+    /// they should be restored.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn entries_with_state(&self) -> Vec<(&Entry<K, V>, EntryState)> {
+        self.apply_reads();
+
+        let mut saved = vec![false; self.entries.len()];
+        let mut entries = Vec::with_capacity(self.len());
+
+        for (slot, placement) in self.policy.placements() {
+            if let Some(entry) = self.entries.get(slot) {
+                saved[slot] = true;
+                let state = EntryState {
+                    placement,
+                    priority: self.policy.priority_of(slot),
+                    pinned: false,
+                };
+                entries.push((entry, state));
             }
-            EntryPointer::SmallQueue(index) => {
-                let entry = self
-                    .small_queue
-                    .get(*index)
-                    .expect("an entry must exist for an entry pointer");
-                Self::update_access_count(entry);
-                Some(entry.value.clone())
+        }
+
+        // pinned entries and entries the policy did not report placements for
+        for (slot, entry) in self.entries.iter().enumerate() {
+            if let (Some(entry), false) = (entry, saved[slot]) {
+                let state = EntryState {
+                    placement: Placement::SmallQueue { frequency: 0 },
+                    priority: self.policy.priority_of(slot),
+                    pinned: self.pinned[slot],
+                };
+                entries.push((entry, state));
             }
         }
+
+        entries
     }
 
-    fn update_access_count(entry: &Entry<K, V>) {
-        let current_val = entry.get_num_accessed();
+    #[cfg(feature = "persistence")]
+    pub(crate) fn ghosts(&self) -> Vec<Ghost> {
+        self.policy.ghosts()
+    }
 
-        if current_val >= 3 {
+    /// Applies the reads buffered by the [`ReadTable`] of the shard to the eviction policy. Reads
+    /// of entries that were removed or pinned in the meantime are skipped.
+    pub(crate) fn apply_reads(&self) {
+        let Some(reads) = self.entries.reads() else {
             return;
-        }
+        };
 
-        entry.increment_num_accessed(current_val);
+        reads.drain_reads(|slot, hash| {
+            let read_entry = self
+                .entries
+                .get(slot)
+                .is_some_and(|entry| entry.hash == hash);
+            if read_entry && !self.pinned[slot] {
+                self.policy.on_access(slot);
+            }
+        });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entry_pointers.len()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn pinned_len(&self) -> usize {
+        self.pinned_len
     }
-}
 
-impl<K, V, S> Shard<K, V, S> {
     pub(crate) fn hit_count(&self) -> u64 {
-        self.counters.hit_count()
+        let lock_free_hits = self.entries.reads().map_or(0, ReadTable::hit_count);
+        self.counters.hit_count() + lock_free_hits
     }
 
     pub(crate) fn miss_count(&self) -> u64 {
-        self.counters.miss_count()
+        let lock_free_misses = self.entries.reads().map_or(0, ReadTable::miss_count);
+        self.counters.miss_count() + lock_free_misses
     }
 
     pub(crate) fn eviction_count(&self) -> u64 {
@@ -230,6 +657,351 @@ impl<K, V, S> Shard<K, V, S> {
     }
 
     pub(crate) fn reset_counters(&self) {
-        self.counters.reset()
+        self.counters.reset();
+        if let Some(reads) = self.entries.reads() {
+            reads.reset_counters();
+        }
+    }
+
+    pub(crate) fn take_policy_stats(&self, stats: &mut ShardStats) {
+        self.policy.take_stats(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gdsf, S3Fifo};
+
+    fn shard(capacity: usize) -> Shard<u64, u64> {
+        let make_policy: PolicyFactory =
+            Arc::new(|capacity| Box::new(S3Fifo::with_capacity(capacity)));
+        Shard::with_capacity_and_policy(capacity, make_policy, 50)
+    }
+
+    #[test]
+    fn it_never_exceeds_its_capacity() {
+        // given
+        let mut shard = shard(10);
+
+        // when
+        for i in 0..100 {
+            shard.insert(i, i, i);
+        }
+
+        // then
+        assert_eq!(shard.len(), 10);
+        assert_eq!(shard.eviction_count(), 90);
+    }
+
+    #[test]
+    fn it_replaces_values_without_evicting() {
+        // given
+        let mut shard = shard(2);
+        shard.insert(1, 1, 1);
+        shard.insert(2, 2, 2);
+
+        // when
+        for i in 0..10 {
+            shard.insert(2, 2, i);
+        }
+
+        // then
+        assert_eq!(shard.get(1, &1), Some(1));
+        assert_eq!(shard.get(2, &2), Some(9));
+        assert_eq!(shard.eviction_count(), 0);
+    }
+
+    #[test]
+    fn it_keeps_the_position_of_entries_updated_in_place() {
+        // given
+        let mut shard = shard(2).update_in_place();
+        shard.insert(1, 1, 1);
+        shard.insert(2, 2, 2);
+
+        // when
+        shard.insert(1, 1, 10);
+        shard.insert(3, 3, 3);
+
+        // then
+        assert_eq!(shard.get(1, &1), None);
+        assert_eq!(shard.get(2, &2), Some(2));
+        assert_eq!(shard.eviction_count(), 1);
+    }
+
+    #[test]
+    fn it_moves_updated_entries_to_the_back_by_default() {
+        // given
+        let mut shard = shard(2);
+        shard.insert(1, 1, 1);
+        shard.insert(2, 2, 2);
+
+        // when
+        shard.insert(1, 1, 10);
+        shard.insert(3, 3, 3);
+
+        // then
+        assert_eq!(shard.get(1, &1), Some(10));
+        assert_eq!(shard.get(2, &2), None);
+    }
+
+    #[test]
+    fn it_never_evicts_pinned_entries() {
+        // given
+        let mut shard = shard(10);
+        shard.insert_pinned(1_000, 1_000, 1_000).unwrap();
+        shard.insert(1_001, 1_001, 1_001);
+        shard.pin(1_001, &1_001).unwrap();
+
+        // when
+        for i in 0..100 {
+            shard.insert(i, i, i);
+        }
+
+        // then
+        assert_eq!(shard.get(1_000, &1_000), Some(1_000));
+        assert_eq!(shard.get(1_001, &1_001), Some(1_001));
+        assert_eq!(shard.len(), 10);
+        assert_eq!(shard.pinned_len(), 2);
+    }
+
+    #[test]
+    fn it_limits_the_number_of_pinned_entries() {
+        // given
+        let mut shard = shard(4);
+        shard.insert_pinned(1, 1, 1).unwrap();
+        shard.insert_pinned(2, 2, 2).unwrap();
+        shard.insert(3, 3, 3);
+
+        // when
+        let inserted = shard.insert_pinned(4, 4, 4);
+        let pinned = shard.pin(3, &3);
+
+        // then
+        assert_eq!(inserted, Err(PinError::LimitReached));
+        assert_eq!(pinned, Err(PinError::LimitReached));
+        assert_eq!(shard.get(4, &4), None);
+        assert_eq!(shard.pin(5, &5), Err(PinError::NotFound));
+    }
+
+    #[test]
+    fn it_evicts_unpinned_entries_again() {
+        // given
+        let mut shard = shard(2);
+        shard.insert_pinned(1, 1, 1).unwrap();
+
+        // when
+        let unpinned = shard.unpin(1, &1);
+        for i in 2..10 {
+            shard.insert(i, i, i);
+        }
+
+        // then
+        assert!(unpinned);
+        assert!(!shard.unpin(1, &1));
+        assert_eq!(shard.get(1, &1), None);
+        assert_eq!(shard.pinned_len(), 0);
+    }
+
+    #[test]
+    fn it_keeps_the_priority_of_unpinned_entries() {
+        // given
+        let mut shard = shard(10);
+        shard.insert_with_priority(1, 1, 1, Priority::High);
+        shard.pin(1, &1).unwrap();
+        for i in 2..6 {
+            shard.insert_pinned(i, i, i).unwrap();
+        }
+
+        // when
+        shard.set_capacity(8);
+        shard.unpin(1, &1);
+        for i in 10..100 {
+            shard.insert(i, i, i);
+        }
+
+        // then
+        assert_eq!(shard.get(1, &1), Some(1));
+    }
+
+    #[test]
+    fn it_keeps_entries_pinned_when_replacing_values() {
+        // given
+        let mut shard = shard(2);
+        shard.insert_pinned(1, 1, 1).unwrap();
+
+        // when
+        let previous = shard.insert(1, 1, 2);
+        for i in 2..10 {
+            shard.insert(i, i, i);
+        }
+
+        // then
+        assert_eq!(previous, Some(1));
+        assert_eq!(shard.get(1, &1), Some(2));
+    }
+
+    #[test]
+    fn it_shrinks_in_eviction_order() {
+        // given
+        let mut shard = shard(10);
+        for i in 0..10 {
+            shard.insert(i, i, i);
+        }
+        for i in 5..10 {
+            shard.get(i, &i);
+            shard.get(i, &i);
+        }
+
+        // when
+        shard.set_capacity(5);
+
+        // then
+        assert_eq!(shard.capacity(), 5);
+        assert_eq!(shard.len(), 5);
+        assert_eq!(shard.eviction_count(), 5);
+        for i in 5..10 {
+            assert_eq!(shard.get(i, &i), Some(i));
+        }
+    }
+
+    #[test]
+    fn it_keeps_evicting_after_shrinking() {
+        // given
+        let mut shard = shard(10);
+        for i in 0..10 {
+            shard.insert(i, i, i);
+        }
+        shard.set_capacity(3);
+
+        // when
+        for i in 10..20 {
+            shard.insert(i, i, i);
+        }
+
+        // then
+        assert_eq!(shard.len(), 3);
+        assert_eq!(shard.get(19, &19), Some(19));
+    }
+
+    #[test]
+    fn it_grows_without_evicting() {
+        // given
+        let mut shard = shard(2);
+        shard.insert(1, 1, 1);
+        shard.insert(2, 2, 2);
+
+        // when
+        shard.set_capacity(4);
+        shard.insert(3, 3, 3);
+        shard.insert(4, 4, 4);
+
+        // then
+        assert_eq!(shard.len(), 4);
+        assert_eq!(shard.eviction_count(), 0);
+        assert_eq!(shard.get(1, &1), Some(1));
+        assert_eq!(shard.get(4, &4), Some(4));
+    }
+
+    #[test]
+    fn it_keeps_the_policy_state_when_shrinking_into_free_slots() {
+        // given
+        let make_policy: PolicyFactory =
+            Arc::new(|capacity| Box::new(Gdsf::with_capacity(capacity)));
+        let mut shard: Shard<u64, u64> = Shard::with_capacity_and_policy(4, make_policy, 50);
+        shard.insert_with_cost(0, 0, 0, 1_000, 1);
+        shard.insert(1, 1, 1);
+
+        // when
+        shard.set_capacity(2);
+        for i in 2..100 {
+            shard.insert(i, i, i);
+        }
+
+        // then
+        assert_eq!(shard.capacity(), 2);
+        assert_eq!(shard.get(0, &0), Some(0));
+    }
+
+    #[test]
+    fn it_tracks_its_unused_capacity() {
+        // given
+        let unused_capacity = Arc::new(AtomicUsize::new(0));
+        let mut shard = shard(4);
+        shard.track_unused_capacity(unused_capacity.clone());
+
+        // when
+        shard.insert(1, 1, 1);
+        shard.insert(1, 1, 2);
+        let after_insert = unused_capacity.load(Ordering::Acquire);
+        shard.set_capacity(10);
+
+        // then
+        assert_eq!(after_insert, 3);
+        assert_eq!(unused_capacity.load(Ordering::Acquire), 9);
+        assert_eq!(shard.unused_capacity(), 9);
+    }
+
+    #[test]
+    fn it_keeps_pinned_entries_when_shrinking() {
+        // given
+        let mut shard = shard(10);
+        for i in 0..10 {
+            shard.insert(i, i, i);
+        }
+        shard.pin(9, &9).unwrap();
+        shard.pin(8, &8).unwrap();
+
+        // when
+        shard.set_capacity(0);
+
+        // then
+        assert_eq!(shard.capacity(), 3);
+        assert_eq!(shard.get(8, &8), Some(8));
+        assert_eq!(shard.get(9, &9), Some(9));
+        assert_eq!(shard.pinned_len(), 2);
+    }
+
+    #[test]
+    fn it_lends_half_of_its_free_slots() {
+        // given
+        let mut lender = shard(8);
+        for i in 0..4 {
+            lender.insert(i, i, i);
+        }
+
+        // when
+        let lent = lender.lend_capacity();
+
+        // then
+        assert_eq!(lent, 2);
+        assert_eq!(lender.capacity(), 6);
+        assert_eq!(lender.unused_capacity(), 2);
+        assert_eq!((0..4).filter_map(|i| lender.get(i, &i)).count(), 4);
+    }
+
+    #[test]
+    fn it_keeps_the_costs_of_entries_when_borrowing_capacity() {
+        // given
+        let make_policy: PolicyFactory =
+            Arc::new(|capacity| Box::new(Gdsf::with_capacity(capacity)));
+        let mut borrower: Shard<u64, u64> = Shard::with_capacity_and_policy(2, make_policy, 50);
+        let mut lender = shard(4);
+        borrower.insert_with_cost(0, 0, 0, 100, 1);
+        borrower.insert(1, 1, 1);
+
+        // when
+        let lent = lender.lend_capacity();
+        borrower.borrow_capacity(lent);
+        for i in 2..20 {
+            borrower.insert(i, i, i);
+        }
+
+        // then
+        assert_eq!(lent, 2);
+        assert_eq!(lender.capacity(), 2);
+        assert_eq!(borrower.capacity(), 4);
+        assert_eq!(borrower.len(), 4);
+        assert_eq!(borrower.get(0, &0), Some(0));
     }
 }
@@ -0,0 +1,87 @@
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Error returned by [`crate::Cache::try_get`] and [`crate::Cache::try_insert`] when the shard of
+/// the key is locked by another thread.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub struct ContentionError;
+
+impl Display for ContentionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "shard is locked by another thread")
+    }
+}
+
+impl Error for ContentionError {}
+
+/// The reader-writer lock of a shard.
+///
+/// Every read that locks the shard updates the reader count of the lock, so when many cores read
+/// from the same shard, the cache line of the lock bounces between them. Caches with
+/// [`crate::Config::lock_free_reads`] only lock a shard to apply buffered reads, and only if no
+/// writer holds the lock.
+#[derive(Debug)]
+pub(crate) struct ShardLock<T>(RwLock<T>);
+
+pub(crate) type ShardReadGuard<'a, T> = RwLockReadGuard<'a, T>;
+
+pub(crate) type ShardWriteGuard<'a, T> = RwLockWriteGuard<'a, T>;
+
+impl<T> ShardLock<T> {
+    pub(crate) fn new(value: T) -> ShardLock<T> {
+        ShardLock(RwLock::new(value))
+    }
+
+    pub(crate) fn read(&self) -> ShardReadGuard<'_, T> {
+        self.0.read()
+    }
+
+    /// Locks the shard for reading, unless a writer holds or waits for the lock.
+    pub(crate) fn try_read(&self) -> Result<ShardReadGuard<'_, T>, ContentionError> {
+        self.0.try_read().ok_or(ContentionError)
+    }
+
+    /// Locks the shard for writing, unless any other thread holds the lock.
+    pub(crate) fn try_write(&self) -> Result<ShardWriteGuard<'_, T>, ContentionError> {
+        self.0.try_write().ok_or(ContentionError)
+    }
+
+    pub(crate) fn write(&self) -> ShardWriteGuard<'_, T> {
+        self.0.write()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_and_writes_through_the_lock() {
+        // given
+        let lock = ShardLock::new(1);
+
+        // when
+        *lock.write() += 1;
+
+        // then
+        assert_eq!(*lock.read(), 2);
+    }
+
+    #[test]
+    fn it_reports_contention() {
+        // given
+        let lock = ShardLock::new(1);
+
+        // when
+        let guard = lock.write();
+
+        // then
+        assert!(lock.try_read().is_err());
+        assert!(lock.try_write().is_err());
+        drop(guard);
+        assert!(lock.try_read().is_ok());
+        assert!(lock.try_write().is_ok());
+    }
+}
@@ -0,0 +1,174 @@
+const NIL: usize = usize::MAX;
+
+/// A doubly linked list of slot indices.
+///
+/// The links are stored in vectors indexed by slot, so all operations, including removals from
+/// the middle of the list, run in constant time and without allocations.
+#[derive(Debug)]
+pub(crate) struct SlotList {
+    front: usize,
+    back: usize,
+    prev: Vec<usize>,
+    next: Vec<usize>,
+    linked: Vec<bool>,
+}
+
+impl SlotList {
+    pub(crate) fn with_capacity(capacity: usize) -> SlotList {
+        SlotList {
+            front: NIL,
+            back: NIL,
+            prev: vec![NIL; capacity],
+            next: vec![NIL; capacity],
+            linked: vec![false; capacity],
+        }
+    }
+
+    /// Changes the number of slots. Removed slots must not be part of the list.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.prev.resize(capacity, NIL);
+        self.next.resize(capacity, NIL);
+        self.linked.resize(capacity, false);
+    }
+
+    pub(crate) fn contains(&self, slot: usize) -> bool {
+        self.linked[slot]
+    }
+
+    /// Returns the slot at the front of the list, i.e. the one that was pushed first.
+    pub(crate) fn front(&self) -> Option<usize> {
+        (self.front != NIL).then_some(self.front)
+    }
+
+    /// Returns the slot that follows `slot`, i.e. the one that was pushed after it.
+    pub(crate) fn next(&self, slot: usize) -> Option<usize> {
+        let next = self.next[slot];
+        (next != NIL).then_some(next)
+    }
+
+    /// Adds a slot to the back of the list.
+    ///
+    /// ## Panics
+    /// Panics if the slot is already part of the list.
+    pub(crate) fn push_back(&mut self, slot: usize) {
+        assert!(!self.linked[slot], "slot {slot} is already linked");
+
+        self.prev[slot] = self.back;
+        self.next[slot] = NIL;
+
+        if self.back == NIL {
+            self.front = slot;
+        } else {
+            self.next[self.back] = slot;
+        }
+
+        self.back = slot;
+        self.linked[slot] = true;
+    }
+
+    /// Removes the slot at the front of the list and returns it.
+    pub(crate) fn pop_front(&mut self) -> Option<usize> {
+        let slot = self.front()?;
+        self.remove(slot);
+        Some(slot)
+    }
+
+    /// Removes a slot from the list. Does nothing if the slot is not part of the list.
+    pub(crate) fn remove(&mut self, slot: usize) {
+        if !self.linked[slot] {
+            return;
+        }
+
+        let prev = self.prev[slot];
+        let next = self.next[slot];
+
+        if prev == NIL {
+            self.front = next;
+        } else {
+            self.next[prev] = next;
+        }
+
+        if next == NIL {
+            self.back = prev;
+        } else {
+            self.prev[next] = prev;
+        }
+
+        self.prev[slot] = NIL;
+        self.next[slot] = NIL;
+        self.linked[slot] = false;
+    }
+
+    /// Returns an iterator over the slots of the list, from front to back.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut current = self.front();
+        std::iter::from_fn(move || {
+            let slot = current?;
+            current = self.next(slot);
+            Some(slot)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_iterates_from_front_to_back() {
+        // given
+        let mut list = SlotList::with_capacity(3);
+
+        // when
+        list.push_back(2);
+        list.push_back(0);
+        list.push_back(1);
+
+        // then
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn it_removes_slots_from_the_middle() {
+        // given
+        let mut list = SlotList::with_capacity(3);
+        list.push_back(0);
+        list.push_back(1);
+        list.push_back(2);
+
+        // when
+        list.remove(1);
+
+        // then
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(list.next(0), Some(2));
+    }
+
+    #[test]
+    fn it_pops_slots_from_the_front() {
+        // given
+        let mut list = SlotList::with_capacity(2);
+        list.push_back(1);
+        list.push_back(0);
+
+        // when
+        let popped = (list.pop_front(), list.pop_front(), list.pop_front());
+
+        // then
+        assert_eq!(popped, (Some(1), Some(0), None));
+        assert!(!list.contains(1));
+    }
+
+    #[test]
+    fn it_ignores_removal_of_unlinked_slots() {
+        // given
+        let mut list = SlotList::with_capacity(2);
+        list.push_back(0);
+
+        // when
+        list.remove(1);
+
+        // then
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![0]);
+    }
+}
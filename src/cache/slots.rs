@@ -0,0 +1,142 @@
+use crate::cache::entry::Entry;
+use crate::cache::read_table::ReadTable;
+use std::sync::Arc;
+
+/// The entries of a shard, indexed by slot.
+///
+/// Entries are stored in their slots. Shards with lock-free reads store them behind an [`Arc`]
+/// instead and publish every change to their [`ReadTable`], which shares the entries, so keys are
+/// still stored only once.
+#[derive(Debug)]
+pub(crate) enum Slots<K, V> {
+    Owned(Vec<Option<Entry<K, V>>>),
+    Shared {
+        entries: Vec<Option<Arc<Entry<K, V>>>>,
+        reads: Arc<ReadTable<K, V>>,
+    },
+}
+
+impl<K, V> Slots<K, V> {
+    pub(crate) fn with_capacity(capacity: usize) -> Slots<K, V> {
+        let mut entries = Vec::with_capacity(capacity);
+        entries.resize_with(capacity, || None);
+        Slots::Owned(entries)
+    }
+
+    /// Creates empty slots whose entries are published to `reads`.
+    pub(crate) fn shared(capacity: usize, reads: Arc<ReadTable<K, V>>) -> Slots<K, V> {
+        let mut entries = Vec::with_capacity(capacity);
+        entries.resize_with(capacity, || None);
+        reads.rebuild(&entries);
+        Slots::Shared { entries, reads }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Slots::Owned(entries) => entries.len(),
+            Slots::Shared { entries, .. } => entries.len(),
+        }
+    }
+
+    /// Returns the entry in `slot`, or [`None`] if the slot is empty or does not exist.
+    pub(crate) fn get(&self, slot: usize) -> Option<&Entry<K, V>> {
+        match self {
+            Slots::Owned(entries) => entries.get(slot)?.as_ref(),
+            Slots::Shared { entries, .. } => entries.get(slot)?.as_deref(),
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Option<&Entry<K, V>>> {
+        (0..self.len()).map(|slot| self.get(slot))
+    }
+
+    /// Returns the read table the entries are published to.
+    pub(crate) fn reads(&self) -> Option<&ReadTable<K, V>> {
+        match self {
+            Slots::Owned(_) => None,
+            Slots::Shared { reads, .. } => Some(reads),
+        }
+    }
+
+    /// Stores an entry in an empty slot.
+    pub(crate) fn insert(&mut self, slot: usize, entry: Entry<K, V>) {
+        match self {
+            Slots::Owned(entries) => entries[slot] = Some(entry),
+            Slots::Shared { entries, reads } => {
+                let entry = Arc::new(entry);
+                reads.insert(slot, &entry);
+                entries[slot] = Some(entry);
+            }
+        }
+    }
+
+    /// Removes the entry from `slot`.
+    pub(crate) fn remove(&mut self, slot: usize) {
+        match self {
+            Slots::Owned(entries) => entries[slot] = None,
+            Slots::Shared { entries, reads } => {
+                if let Some(entry) = entries[slot].take() {
+                    reads.remove(slot, entry.hash);
+                }
+            }
+        }
+    }
+
+    /// Adds empty slots to or removes empty slots from the end.
+    pub(crate) fn resize(&mut self, capacity: usize) {
+        match self {
+            Slots::Owned(entries) => entries.resize_with(capacity, || None),
+            Slots::Shared { entries, reads } => {
+                entries.resize_with(capacity, || None);
+                reads.rebuild(entries);
+            }
+        }
+    }
+
+    /// Moves the entry of every slot to the slot given by `new_slots` and drops the entries
+    /// without a new slot.
+    pub(crate) fn relocate(&mut self, capacity: usize, new_slots: &[Option<usize>]) {
+        match self {
+            Slots::Owned(entries) => *entries = relocated(entries, capacity, new_slots),
+            Slots::Shared { entries, reads } => {
+                *entries = relocated(entries, capacity, new_slots);
+                reads.rebuild(entries);
+            }
+        }
+    }
+}
+
+impl<K, V: Clone> Slots<K, V> {
+    /// Replaces the entry in an occupied slot and returns the previous value.
+    pub(crate) fn replace(&mut self, slot: usize, entry: Entry<K, V>) -> Option<V> {
+        match self {
+            Slots::Owned(entries) => entries[slot].replace(entry).map(|entry| entry.value),
+            Slots::Shared { entries, reads } => {
+                let entry = Arc::new(entry);
+                reads.replace(slot, &entry);
+                // readers may still hold the previous entry
+                entries[slot].replace(entry).map(|entry| {
+                    Arc::try_unwrap(entry)
+                        .map_or_else(|entry| entry.value.clone(), |entry| entry.value)
+                })
+            }
+        }
+    }
+}
+
+fn relocated<T>(
+    entries: &mut Vec<Option<T>>,
+    capacity: usize,
+    new_slots: &[Option<usize>],
+) -> Vec<Option<T>> {
+    let mut relocated = Vec::with_capacity(capacity);
+    relocated.resize_with(capacity, || None);
+
+    for (entry, new_slot) in entries.drain(..).zip(new_slots) {
+        if let (Some(entry), Some(new_slot)) = (entry, new_slot) {
+            relocated[*new_slot] = Some(entry);
+        }
+    }
+
+    relocated
+}
@@ -2,13 +2,63 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Cache performance statistics.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Stats {
     pub miss_count: u64,
     pub hit_count: u64,
     pub eviction_count: u64,
+    /// The number of inserted keys that were found in a ghost queue of the eviction policy.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ghost_hit_count: u64,
+    /// The number of inserted keys that were found in the ghost queue of main queue evictions.
+    /// See [`crate::Config::remember_main_queue_evictions`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub main_queue_ghost_hit_count: u64,
     pub millis_elapsed: u128,
+    /// Statistics of the individual shards, in shard order.
+    pub shards: Vec<ShardStats>,
 }
 
+/// Performance statistics of a single cache shard.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct ShardStats {
+    pub miss_count: u64,
+    pub hit_count: u64,
+    pub eviction_count: u64,
+    /// The number of inserted keys that were found in a ghost queue of the eviction policy.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ghost_hit_count: u64,
+    /// The number of inserted keys that were found in the ghost queue of main queue evictions.
+    /// See [`crate::Config::remember_main_queue_evictions`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub main_queue_ghost_hit_count: u64,
+    /// The number of items stored in the shard.
+    pub len: usize,
+    /// The number of items the shard can hold.
+    pub capacity: usize,
+    /// The number of pinned items stored in the shard.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pinned_len: usize,
+}
+
+impl ShardStats {
+    /// Adds the counts of `other` to these statistics.
+    pub(crate) fn add_counts(&mut self, other: &ShardStats) {
+        self.miss_count += other.miss_count;
+        self.hit_count += other.hit_count;
+        self.eviction_count += other.eviction_count;
+        self.ghost_hit_count += other.ghost_hit_count;
+        self.main_queue_ghost_hit_count += other.main_queue_ghost_hit_count;
+    }
+}
+
+/// The counters of a shard.
+///
+/// Hits and misses are counted here while the shard is locked for reading. Reads without locking
+/// the shard are counted by its read table instead.
 #[derive(Debug, Default)]
 pub(crate) struct Counters {
     hit_count: AtomicU64,
@@ -47,3 +97,38 @@ impl Counters {
         self.eviction_count.store(0, Ordering::Release);
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_roundtrips_stats_through_json() {
+        // given
+        let stats = Stats {
+            miss_count: 1,
+            hit_count: 2,
+            eviction_count: 3,
+            ghost_hit_count: 7,
+            main_queue_ghost_hit_count: 8,
+            millis_elapsed: 4,
+            shards: vec![ShardStats {
+                miss_count: 1,
+                hit_count: 2,
+                eviction_count: 3,
+                ghost_hit_count: 7,
+                main_queue_ghost_hit_count: 8,
+                len: 5,
+                capacity: 6,
+                pinned_len: 1,
+            }],
+        };
+
+        // when
+        let json = serde_json::to_string(&stats).unwrap();
+        let deserialized: Stats = serde_json::from_str(&json).unwrap();
+
+        // then
+        assert_eq!(deserialized, stats);
+    }
+}
@@ -0,0 +1,137 @@
+use std::cell::Cell;
+use std::num::NonZero;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// The number of stripes of state that eviction policies write while their shard is locked for
+/// reading.
+pub(crate) const STRIPE_COUNT: usize = 8;
+
+/// The maximum number of stripes of state that is written without locking the shard.
+const MAX_THREAD_STRIPE_COUNT: usize = 64;
+
+static NEXT_THREAD_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Copies of a value that concurrent threads update without writing to the same cache line.
+///
+/// Every thread uses the stripe of its thread index. Thread indices are handed out in the order in
+/// which threads first access a striped value, so up to [`STRIPE_COUNT`] threads use different
+/// stripes.
+#[derive(Debug)]
+pub(crate) struct Striped<T> {
+    stripes: Box<[CachePadded<T>]>,
+}
+
+/// Aligns a value to the size of two cache lines, since some CPUs prefetch cache lines in pairs.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+impl<T: Default> Striped<T> {
+    pub(crate) fn new(stripe_count: usize) -> Striped<T> {
+        Striped {
+            stripes: (0..stripe_count.max(1))
+                .map(|_| CachePadded::default())
+                .collect(),
+        }
+    }
+}
+
+impl<T> Striped<T> {
+    /// Returns the stripe of the current thread.
+    pub(crate) fn get(&self) -> &T {
+        &self.stripes[thread_index() % self.stripes.len()].0
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.stripes.iter().map(|stripe| &stripe.0)
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.stripes.iter_mut().map(|stripe| &mut stripe.0)
+    }
+}
+
+/// Returns the number of stripes of state that is written without locking the shard: one per
+/// available core, up to 64.
+pub(crate) fn thread_stripe_count() -> usize {
+    thread::available_parallelism()
+        .map_or(1, NonZero::get)
+        .next_power_of_two()
+        .min(MAX_THREAD_STRIPE_COUNT)
+}
+
+fn thread_index() -> usize {
+    THREAD_INDEX.with(|index| {
+        index.get().unwrap_or_else(|| {
+            let next = NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed);
+            index.set(Some(next));
+            next
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicU64;
+    use std::thread;
+
+    #[test]
+    fn it_keeps_the_stripe_of_a_thread() {
+        // given
+        let striped: Striped<AtomicU64> = Striped::new(STRIPE_COUNT);
+
+        // when
+        for _ in 0..10 {
+            striped.get().fetch_add(1, Ordering::Relaxed);
+        }
+
+        // then
+        let counts: Vec<_> = striped.iter().map(|c| c.load(Ordering::Relaxed)).collect();
+        assert_eq!(counts.iter().sum::<u64>(), 10);
+        assert!(counts.contains(&10), "{counts:?}");
+    }
+
+    #[test]
+    fn it_collects_the_stripes_of_all_threads() {
+        // given
+        let striped: Arc<Striped<AtomicU64>> = Arc::new(Striped::new(STRIPE_COUNT));
+
+        // when
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let striped = striped.clone();
+                thread::spawn(move || {
+                    for _ in 0..1_000 {
+                        striped.get().fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // then
+        let total: u64 = striped.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+        assert_eq!(total, 16_000);
+    }
+
+    #[test]
+    fn it_keeps_stripes_on_separate_cache_lines() {
+        // given
+        let striped: Striped<AtomicU64> = Striped::new(2);
+
+        // when
+        let addresses: Vec<_> = striped.iter().map(|c| c as *const _ as usize).collect();
+
+        // then
+        assert!(addresses[1] - addresses[0] >= 128);
+    }
+}
@@ -7,9 +7,31 @@
 //!
 //! - Thread-safe by default - no need for explicit synchronization
 //! - S3-FIFO eviction algorithm for optimal cache performance
-//! - Sharded design to reduce contention during concurrent access
+//! - SIEVE, LRU and CLOCK as alternative built-in eviction algorithms, selected through
+//!   [`Config::policy`]
+//! - Cost-aware eviction with GDSF and [`Cache::insert_with_cost`]
+//! - Optional TinyLFU admission filter, selected through [`Config::admission`]
+//! - Optional adaptive sizing of the S3-FIFO small queue, see [`Config::adaptive_small_queue`]
+//! - Optional FIFO ghost queue of key fingerprints, see [`Config::ghost_queue`]
+//! - Runtime resizing without losing entries, see [`Cache::set_capacity`]
+//! - Priority classes, see [`Cache::insert_with_priority`]
+//! - Pinning of entries that must never be evicted, see [`Cache::insert_pinned`]
+//! - Pluggable eviction policies through the [`EvictionPolicy`] trait
+//! - Sharded design to reduce contention during concurrent access, see [`Config::shard_count`]
+//! - Optional exact global capacity across shards, see [`Config::exact_capacity`]
+//! - Optional reads that do not lock their shard, see [`Config::lock_free_reads`]
+//! - Non-blocking [`Cache::try_get`] and [`Cache::try_insert`] that report contention
+//! - Optional in-place updates that keep the position of refreshed entries, see
+//!   [`Config::update_in_place`]
 //! - No unsafe code
 //!
+//! # Optional features
+//!
+//! - `serde`: implements `Serialize` and `Deserialize` for [`Config`], [`Stats`] and
+//!   [`ShardStats`]
+//! - `persistence`: adds [`Cache::save_to`] and [`Cache::load_from`] to save the cache contents to
+//!   a file and restore them after a restart
+//!
 //! # Safety
 //!
 //! This crate is designed to be safe and easy to use:
@@ -100,4 +122,14 @@
 pub mod cache;
 
 pub use cache::Cache;
-pub use cache::stats::Stats;
+pub use cache::config::{Config, ConfigBuilder};
+#[cfg(feature = "persistence")]
+pub use cache::persistence::{LoadReport, PersistenceError};
+pub use cache::pin::PinError;
+pub use cache::placement::Placement;
+pub use cache::policy::{
+    Admission, Clock, EvictionPolicy, Gdsf, GhostQueue, Insertion, Lru, Policy, Priority, S3Fifo,
+    Sieve,
+};
+pub use cache::shard_lock::ContentionError;
+pub use cache::stats::{ShardStats, Stats};